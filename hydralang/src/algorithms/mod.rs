@@ -1,14 +1,11 @@
+use num_bigint::BigInt;
+use num_traits::{Signed, Zero};
 
-pub fn gcd(a: i64, b: i64) -> i64{
-    fn _gcd(a: i64, b: i64) -> i64 {
-        if b == 0 { a } else { _gcd(b, a % b) }
+/// Always positive, gcd(0, 0) is 0
+pub fn gcd(a: &BigInt, b: &BigInt) -> BigInt {
+    fn _gcd(a: BigInt, b: BigInt) -> BigInt {
+        if b.is_zero() { a } else { let r = &a % &b; _gcd(b, r) }
     }
-    let a = a.abs();
-    let b = b.abs();
 
-    if a > b {
-        _gcd(a, b)
-    } else {
-        _gcd(b, a)
-    }
-} 
\ No newline at end of file
+    _gcd(a.abs(), b.abs())
+}
@@ -1,12 +1,12 @@
 use crate::model::expression::Node;
-use crate::model::function::RustInternalFunction;
+use crate::model::function::{RustInternalFunction, InternalFunction};
 use crate::model::{Script, function::FunctionDef};
 use crate::model::Expression;
 
 pub struct RustInternalFunctionBuilder {
     args: Vec<String>,
     name: Option<String>,
-    function: Option<fn(&[Node]) -> Expression>
+    function: Option<InternalFunction>
 }
 
 impl RustInternalFunctionBuilder {
@@ -17,7 +17,7 @@ impl RustInternalFunctionBuilder {
         self
     }
 
-    pub fn function(&mut self, f: fn(&[Node]) -> Expression) -> &mut Self {
+    pub fn function(&mut self, f: InternalFunction) -> &mut Self {
         self.function = Some(f);
         self
     }
@@ -38,11 +38,11 @@ impl RustInternalFunctionBuilder {
 }
 
 pub mod base_internal{
-    use num_traits::Pow;
-
-    use crate::{model::{expression::Node, Expression, number::Number}, visitor::ImmutableExpressionVisitor, traits::DeepEq};
+    use crate::{model::{expression::Node, Expression, Script, number::Number}, visitor::{ImmutableExpressionVisitor, ExpressionModfierVisitor, DefaultSimplifyVisitor, apply_lambda}, traits::DeepEq};
     use crate::model::expression_builder::*;
 
+    const MAX_RANGE_LENGTH: i64 = 1 << 16;
+
     pub struct ExpressionContainsVisitor {
         expected_expr: Expression
     }
@@ -80,60 +80,173 @@ pub mod base_internal{
             false
         }
 
+        fn visit_str(&self, s: &str) -> bool {
+            Expression::new(string(s)).deep_eq(&self.expected_expr)
+        }
+
         fn visit_vec(&self, v: &Vec<Node>) -> bool {
             if Expression::new(vector(v.clone())).deep_eq(&self.expected_expr) { return true; }
             v.iter().any(|n| self.visit_node(n))
         }
 
+        fn visit_matrix(&self, rows: usize, cols: usize, data: &[Node]) -> bool {
+            if Expression::new(matrix(rows, cols, data.to_vec())).deep_eq(&self.expected_expr) { return true; }
+            data.iter().any(|n| self.visit_node(n))
+        }
+
+        fn visit_lambda(&self, params: &[String], body: &Node) -> bool {
+            if Expression::new(Node::Lambda { params: params.to_vec(), body: Box::new(body.clone()) }).deep_eq(&self.expected_expr) { return true; }
+            self.visit_node(body)
+        }
+
+        fn visit_equation(&self, l: &Node, r: &Node) -> bool {
+            if Expression::new(equation(l.clone(), r.clone())).deep_eq(&self.expected_expr) { return true; }
+            self.visit_node(l) | self.visit_node(r)
+        }
+
         fn visit_function_call(&self, name: &String, args: &Vec<Node>) -> bool {
             if Expression::new(func_call(name.clone(), args.clone())).deep_eq(&self.expected_expr) { return true; }
             args.iter().any(|n| self.visit_node(n))
         }
     }
 
-    pub fn add_nums(args: &[Node]) -> Expression {
+    pub fn add_nums(args: &[Node], _script: &Script) -> Expression {
         match args {
             [Node::Num(a_value), Node::Num(b_value)] => Expression::new(Node::Num(a_value.clone() + b_value.clone())),
             _ => panic!("Unexpected symbols in _addNumbers function")
         }
     }
 
-    pub fn sub_nums(args: &[Node]) -> Expression {
+    pub fn sub_nums(args: &[Node], _script: &Script) -> Expression {
         match args {
             [Node::Num(a_value), Node::Num(b_value)] => Expression::new(Node::Num(a_value.clone() - b_value.clone())),
             _ => panic!("Unexpected symbols in _subtractNumbers function")
         }
     }
 
-    pub fn multiply_nums(args: &[Node]) -> Expression {
+    pub fn multiply_nums(args: &[Node], _script: &Script) -> Expression {
         match args {
             [Node::Num(a_value), Node::Num(b_value)] => Expression::new(Node::Num(a_value.clone() * b_value.clone())),
             _ => panic!("Unexpected symbols in _multiplyNumbers function")
         }
     }
 
-    pub fn exponentiate_nums(args: &[Node]) -> Expression {
+    pub fn divide_nums(args: &[Node], _script: &Script) -> Expression {
+        match args {
+            // Dividing by zero is left for the simplifier to report
+            [Node::Num(a_value), Node::Num(b_value)] => match a_value.checked_div(b_value) {
+                Some(result) => Expression::new(Node::Num(result)),
+                None => unchanged("_divideNumbers", args)
+            },
+            _ => unchanged("_divideNumbers", args)
+        }
+    }
+
+    pub fn negate_num(args: &[Node], _script: &Script) -> Expression {
         match args {
-            [Node::Num(a_value), Node::Num(b_value)] => Expression::new(Node::Num(a_value.clone().pow(b_value.clone()))),
-            _ => panic!("Unexpected symbols in _exponentiateNumbers function")
+            [Node::Num(a_value)] => Expression::new(Node::Num(-a_value.clone())),
+            _ => panic!("Unexpected symbols in _negateNumber function")
         }
     }
 
-    pub fn is_num(args: &[Node]) -> Expression {
+    pub fn exponentiate_nums(args: &[Node], _script: &Script) -> Expression {
+        match args {
+            [Node::Num(a_value), Node::Num(b_value)] => match a_value.pow(b_value) {
+                Some(result) => Expression::new(Node::Num(result)),
+                None => unchanged("_exponentiateNumbers", args)
+            },
+            _ => unchanged("_exponentiateNumbers", args)
+        }
+    }
+
+    // Powers like 2^(1/2) have no exact answer so they're left as they are
+    pub fn is_exact_power(args: &[Node], _script: &Script) -> Expression {
+        match args {
+            [Node::Num(a_value), Node::Num(b_value)] => Expression::new(num(if a_value.pow(b_value).is_some() { 1 } else { 0 })),
+            _ => Expression::new(num(0))
+        }
+    }
+
+    fn compare_nums(args: &[Node], name: &str, predicate: fn(&Number, &Number) -> bool) -> Expression {
+        match args {
+            [Node::Num(a_value), Node::Num(b_value)] => Expression::new(num(if predicate(a_value, b_value) { 1 } else { 0 })),
+            _ => panic!("Unexpected symbols in {} function", name)
+        }
+    }
+
+    pub fn less_than(args: &[Node], _script: &Script) -> Expression { compare_nums(args, "_lessThan", |a, b| a < b) }
+
+    pub fn greater_than(args: &[Node], _script: &Script) -> Expression { compare_nums(args, "_greaterThan", |a, b| a > b) }
+
+    pub fn less_than_or_equal(args: &[Node], _script: &Script) -> Expression { compare_nums(args, "_lessThanOrEqual", |a, b| a <= b) }
+
+    pub fn greater_than_or_equal(args: &[Node], _script: &Script) -> Expression { compare_nums(args, "_greaterThanOrEqual", |a, b| a >= b) }
+
+    // Strings can only be compared for equality, there's no order to them yet
+    pub fn equal(args: &[Node], _script: &Script) -> Expression {
+        match args {
+            [Node::Str(a), Node::Str(b)] => Expression::new(num(if a == b { 1 } else { 0 })),
+            _ => compare_nums(args, "_equal", |a, b| a == b)
+        }
+    }
+
+    pub fn not_equal(args: &[Node], _script: &Script) -> Expression {
+        match args {
+            [Node::Str(a), Node::Str(b)] => Expression::new(num(if a != b { 1 } else { 0 })),
+            _ => compare_nums(args, "_notEqual", |a, b| a != b)
+        }
+    }
+
+    pub fn absolute_value(args: &[Node], _script: &Script) -> Expression {
+        match args {
+            [Node::Num(a_value)] => Expression::new(Node::Num(a_value.clone().abs())),
+            _ => panic!("Unexpected symbols in _absoluteValue function")
+        }
+    }
+
+    // The square root of the sum of squares, which works out the same as any other power so (3, 4) is 5 and (1, 1) is left as 2^(1/2)
+    pub fn magnitude(args: &[Node], _script: &Script) -> Expression {
+        match args {
+            [Node::Vector(v)] => {
+                let sum = v.iter().map(|n| pow(n.clone(), num(2))).reduce(add).unwrap_or(num(0));
+                Expression::new(pow(sum, fraction(1, 2)))
+            },
+            _ => unchanged("_magnitude", args)
+        }
+    }
+
+    pub fn is_vector(args: &[Node], _script: &Script) -> Expression {
+        match args {
+            [Node::Vector(_)] => Expression::new(num(1)),
+            _ => Expression::new(num(0))
+        }
+    }
+
+    pub fn is_num(args: &[Node], _script: &Script) -> Expression {
         match args {
             [Node::Num(_)] => Expression::new(num(1)),
             _ => Expression::new(num(0))
         }
     }
 
-    pub fn gcd_function(args: &[Node]) -> Expression {
+    pub fn is_str(args: &[Node], _script: &Script) -> Expression {
+        match args {
+            [Node::Str(_)] => Expression::new(num(1)),
+            _ => Expression::new(num(0))
+        }
+    }
+
+    pub fn gcd_function(args: &[Node], _script: &Script) -> Expression {
         match args {
-            //[Node::Num(a), Node::Num(b)] => Expression::new(num(gcd(*a, *b))), //TODO: GCD does not work after num changes
-            _ => Expression::new(num(1))
+            [Node::Num(a), Node::Num(b)] => match a.gcd(b) {
+                Some(result) => Expression::new(Node::Num(result)),
+                None => unchanged("_gcd", args)
+            },
+            _ => unchanged("_gcd", args)
         }
     }
 
-    pub fn contains_expr(args: &[Node]) -> Expression {
+    pub fn contains_expr(args: &[Node], _script: &Script) -> Expression {
         match args {
             [a, b] => if ExpressionContainsVisitor::new(Expression::new(b.clone())).visit(Expression::new(a.clone())) {
                 Expression::new(num(1))
@@ -144,29 +257,138 @@ pub mod base_internal{
         }
     }
 
+    // f can be a lambda or the name of a function, None if it's something we don't know how to call
+    fn apply_function(f: &Node, args: Vec<Node>, script: &Script) -> Option<Node> {
+        let applied = match f {
+            Node::Lambda { params, box body } => apply_lambda(params, body, &args, Some(script))?,
+            Node::Var(name) => func_call(name.clone(), args),
+            _ => return None
+        };
+
+        Some(DefaultSimplifyVisitor::new(script).visit_node(applied))
+    }
+
+    // Anything these can't handle is handed back as the original call and left for the user to see
+    fn unchanged(name: &str, args: &[Node]) -> Expression {
+        Expression::new(func_call(name.to_string(), args.to_vec()))
+    }
+
+    pub fn map(args: &[Node], script: &Script) -> Expression {
+        if let [Node::Vector(v), f] = args {
+            if let Some(mapped) = v.iter().map(|n| apply_function(f, vec![n.clone()], script)).collect::<Option<Vec<Node>>>() {
+                return Expression::new(vector(mapped));
+            }
+        }
+        unchanged("map", args)
+    }
+
+    pub fn fold(args: &[Node], script: &Script) -> Expression {
+        if let [Node::Vector(v), init, f] = args {
+            if let Some(folded) = v.iter().try_fold(init.clone(), |acc, n| apply_function(f, vec![acc, n.clone()], script)) {
+                return Expression::new(folded);
+            }
+        }
+        unchanged("fold", args)
+    }
+
+    // Predicates have to come out as 1 or 0 for every element, otherwise we can't tell what belongs in the result
+    pub fn filter(args: &[Node], script: &Script) -> Expression {
+        if let [Node::Vector(v), p] = args {
+            let mut kept = Vec::new();
+            for n in v {
+                match apply_function(p, vec![n.clone()], script) {
+                    Some(result) if result.deep_eq(&num(1)) => kept.push(n.clone()),
+                    Some(result) if result.deep_eq(&num(0)) => {},
+                    _ => return unchanged("filter", args)
+                }
+            }
+            return Expression::new(vector(kept));
+        }
+        unchanged("filter", args)
+    }
+
+    // Pairs up elements, stopping at the end of the shorter vector
+    pub fn zip(args: &[Node], _script: &Script) -> Expression {
+        match args {
+            [Node::Vector(a), Node::Vector(b)] => Expression::new(vector(a.iter().zip(b.iter()).map(|(x, y)| vector(vec![x.clone(), y.clone()])).collect())),
+            _ => unchanged("zip", args)
+        }
+    }
+
+    // Counts from a up to but not including b, ranges longer than MAX_RANGE_LENGTH are left alone rather than running out of memory
+    pub fn range(args: &[Node], _script: &Script) -> Expression {
+        match args {
+            [Node::Num(Number::Int(a)), Node::Num(Number::Int(b))] if (*b as i128 - *a as i128) <= MAX_RANGE_LENGTH as i128 => Expression::new(vector((*a..*b).map(num).collect())),
+            _ => unchanged("range", args)
+        }
+    }
+
+    // Strings are counted in characters rather than bytes, so "Δt" has a length of 2
+    pub fn length(args: &[Node], _script: &Script) -> Expression {
+        match args {
+            [Node::Vector(v)] => Expression::new(num(v.len() as i64)),
+            [Node::Str(s)] => Expression::new(num(s.chars().count() as i64)),
+            _ => unchanged("length", args)
+        }
+    }
+
+    pub fn concat(args: &[Node], _script: &Script) -> Expression {
+        match args {
+            [Node::Str(a), Node::Str(b)] => Expression::new(string(&format!("{}{}", a, b))),
+            [Node::Vector(a), Node::Vector(b)] => Expression::new(vector(a.iter().chain(b.iter()).cloned().collect())),
+            _ => unchanged("concat", args)
+        }
+    }
+
+    pub fn chars(args: &[Node], _script: &Script) -> Expression {
+        match args {
+            [Node::Str(s)] => Expression::new(vector(s.chars().map(|c| string(&c.to_string())).collect())),
+            _ => unchanged("chars", args)
+        }
+    }
+
 }
 
 pub fn base_config() -> Script {
 
     let default_script_hidden_functions = include_str!("resources/base_hidden.hydra");
-    let _default_script = include_str!("resources/base.hydra");
     let _example_script = include_str!("resources/example.hydra");
 
     let function_defs = vec![
         RustInternalFunctionBuilder::new().name("_addNumbers").args(&["a", "b"]).function(base_internal::add_nums).build(),
         RustInternalFunctionBuilder::new().name("_subtractNumbers").args(&["a", "b"]).function(base_internal::sub_nums).build(),
         RustInternalFunctionBuilder::new().name("_multiplyNumbers").args(&["a", "b"]).function(base_internal::multiply_nums).build(),
+        RustInternalFunctionBuilder::new().name("_divideNumbers").args(&["a", "b"]).function(base_internal::divide_nums).build(),
+        RustInternalFunctionBuilder::new().name("_negateNumber").args(&["a"]).function(base_internal::negate_num).build(),
         RustInternalFunctionBuilder::new().name("_exponentiateNumbers").args(&["a", "b"]).function(base_internal::exponentiate_nums).build(),
+        RustInternalFunctionBuilder::new().name("_isExactPower").args(&["a", "b"]).function(base_internal::is_exact_power).build(),
+        RustInternalFunctionBuilder::new().name("_lessThan").args(&["a", "b"]).function(base_internal::less_than).build(),
+        RustInternalFunctionBuilder::new().name("_greaterThan").args(&["a", "b"]).function(base_internal::greater_than).build(),
+        RustInternalFunctionBuilder::new().name("_lessThanOrEqual").args(&["a", "b"]).function(base_internal::less_than_or_equal).build(),
+        RustInternalFunctionBuilder::new().name("_greaterThanOrEqual").args(&["a", "b"]).function(base_internal::greater_than_or_equal).build(),
+        RustInternalFunctionBuilder::new().name("_equal").args(&["a", "b"]).function(base_internal::equal).build(),
+        RustInternalFunctionBuilder::new().name("_notEqual").args(&["a", "b"]).function(base_internal::not_equal).build(),
+        RustInternalFunctionBuilder::new().name("_absoluteValue").args(&["a"]).function(base_internal::absolute_value).build(),
+        RustInternalFunctionBuilder::new().name("_magnitude").args(&["v"]).function(base_internal::magnitude).build(),
         RustInternalFunctionBuilder::new().name("isNum").args(&["arg"]).function(base_internal::is_num).build(),
+        RustInternalFunctionBuilder::new().name("isStr").args(&["arg"]).function(base_internal::is_str).build(),
+        RustInternalFunctionBuilder::new().name("isVector").args(&["arg"]).function(base_internal::is_vector).build(),
         RustInternalFunctionBuilder::new().name("contains").args(&["a", "b"]).function(base_internal::contains_expr).build(),
-        RustInternalFunctionBuilder::new().name("_gcd").args(&["a", "b"]).function(base_internal::gcd_function).build()
+        RustInternalFunctionBuilder::new().name("_gcd").args(&["a", "b"]).function(base_internal::gcd_function).build(),
+        RustInternalFunctionBuilder::new().name("map").args(&["v", "f"]).function(base_internal::map).build(),
+        RustInternalFunctionBuilder::new().name("fold").args(&["v", "init", "f"]).function(base_internal::fold).build(),
+        RustInternalFunctionBuilder::new().name("filter").args(&["v", "p"]).function(base_internal::filter).build(),
+        RustInternalFunctionBuilder::new().name("zip").args(&["a", "b"]).function(base_internal::zip).build(),
+        RustInternalFunctionBuilder::new().name("range").args(&["a", "b"]).function(base_internal::range).build(),
+        RustInternalFunctionBuilder::new().name("length").args(&["v"]).function(base_internal::length).build(),
+        RustInternalFunctionBuilder::new().name("concat").args(&["a", "b"]).function(base_internal::concat).build(),
+        RustInternalFunctionBuilder::new().name("chars").args(&["s"]).function(base_internal::chars).build()
     ];
 
     let mut base_hidden = Script::parse(default_script_hidden_functions).expect("Failed to parse base_hidden.hydra file");
     base_hidden.hide_all_function_defs();
     
 
-    //let mut base = Script::parse(default_script).expect("Failed to parse base.hydra file");
     //let mut base = Script::parse(example_script).unwrap();
     let mut base = Script::new(Vec::new(), Vec::new());
 
@@ -176,3 +398,161 @@ pub fn base_config() -> Script {
     base
 }
 
+
+/// Scripts embedded in the binary which any script can import by name, ie: "use base"
+pub fn stdlib_modules() -> Vec<(&'static str, &'static str)> {
+    vec![
+        ("base", include_str!("resources/base.hydra"))
+    ]
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::{expression_builder::*, error::DSLError, number::Number}, traits::DeepEq};
+
+    fn run(input: &str) -> Script {
+        let mut script = base_config();
+        script.merge(&Script::parse(input).unwrap());
+        script.run().unwrap();
+        script
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let script = run("1 < 2
+            2 <= 1.5
+            3 > 2 + 2
+            2.0 >= 2
+            4 == 2 * 2
+            4 != 4
+            x < 2");
+
+        let expected = [num(1), num(0), num(0), num(1), num(1), num(0), op("<", var("x".to_string()), num(2))];
+        for (i, e) in expected.iter().enumerate() {
+            assert!(script.get_expression(i).unwrap().get_root_node().deep_eq(e), "{}", script.get_expression(i).unwrap().to_string());
+        }
+    }
+
+    #[test]
+    fn test_comparison_guards() {
+        let script = run("fact(n) = n * fact(n - 1) where { n > 0 }
+            fact(0) = 1
+            fact(5)
+            fact(25)");
+
+        assert!(script.get_expression(0).unwrap().get_root_node().deep_eq(&num(120)));
+        assert_eq!(script.get_expression(1).unwrap().to_string(), "15511210043330985984000000");
+    }
+
+    #[test]
+    fn test_fractions() {
+        let script = run("3 * (1 / 3)
+            1/3 + 1/6
+            (2/3)^2
+            x + 1/2
+            1/3 < 1/2
+            gcd(12, 18)
+            gcd(2/3, 4)
+            _gcd(x, 2)
+            _divideNumbers(x, 2)
+            2^-2
+            27^(2/3)
+            2^(1/2)
+            (-4)^(1/2)
+            1.5^2
+            _exponentiateNumbers(2, 1/2)
+            2^(1/2) * 2^(1/2)");
+
+        let expected = ["1", "1/2", "4/9", "x + 1/2", "1", "6", "2/3", "_gcd(x, 2)", "_divideNumbers(x, 2)", "1/4", "9", "2^(1/2)", "(-4)^(1/2)", "2.25", "_exponentiateNumbers(2, 1/2)", "2^(1/2) * 2^(1/2)"];
+        for (i, e) in expected.iter().enumerate() {
+            assert_eq!(script.get_expression(i).unwrap().to_string(), *e);
+        }
+
+        // Other rules for powers still apply when there's no exact answer
+        let script = run("eval(a ^ 2) = square(a)\ny^2\n2^(1/2)");
+        assert_eq!(script.get_expression(0).unwrap().to_string(), "square(y)");
+        assert_eq!(script.get_expression(1).unwrap().to_string(), "2^(1/2)");
+    }
+
+    #[test]
+    fn test_undefined_results() {
+        let error = |input: &str| {
+            let mut script = base_config();
+            script.merge(&Script::parse(input).unwrap());
+            match script.run() {
+                Err(DSLError::RuntimeException { message, expression }) => (message, expression, script),
+                _ => panic!("Expected a runtime error from {}", input)
+            }
+        };
+
+        let (message, expression, script) = error("2 + 3\ny = 4 - 4\n1 / y\n5");
+        assert_eq!((message.as_str(), expression.to_string().as_str()), ("division by zero", "1 / 0"));
+        assert_eq!(script.to_string(), "5\ny = 0\n1 / y\n5\n");
+
+        assert_eq!(error("x / (2 - 2)").1.to_string(), "x / 0");
+        // How a decimal zero is printed depends on the version of BigDecimal, so only its shape is checked
+        let (message, expression, _) = error("0 * (1 / 0.0)");
+        assert_eq!(message, "division by zero");
+        assert!(matches!(expression.get_root_node(), Node::Op(op, box Node::Num(Number::Int(1)), box Node::Num(zero @ Number::Decimal(_))) if op == "/" && zero.is_zero()));
+        assert_eq!(error("(1, 2, 3) / 0").1.to_string(), "(1, 2, 3) / 0");
+        assert_eq!(error("map((1, 0), (x) => 1 / x)").1.to_string(), "1 / 0");
+        assert_eq!(error("a = 0 ^ -1").0, "zero to a negative power");
+
+        let script = run("_divideNumbers(1, 0)\n0 / 2\n0 ^ 0");
+        assert_eq!(script.to_string(), "_divideNumbers(1, 0)\n0\n1\n");
+    }
+
+    #[test]
+    fn test_absolute_values() {
+        let script = run("f(x) = |(x, 2)|
+            |-3|
+            |2.5 - 4|
+            |(3, 4)|
+            |(1, 1)|
+            |(x, 2)|
+            |x|
+            f(0)
+            |(1/2, 2/3)|
+            |(1.5, 2)|
+            _magnitude(3)");
+
+        let expected = ["3", "1.5", "5", "2^(1/2)", "(x^2 + 4)^(1/2)", "|x|", "2", "5/6", "2.5", "_magnitude(3)"];
+        for (i, e) in expected.iter().enumerate() {
+            assert_eq!(script.get_expression(i).unwrap().to_string(), *e);
+        }
+    }
+
+    #[test]
+    fn test_higher_order_builtins() {
+        let script = run("square(x) = x^2
+            add(a, b) = a + b
+            map((1, 2, 3), square)
+            map((1, 2, 3), (x) => x + 1)
+            fold((1, 2, 3, 4), 0, add)
+            fold(range(1, 6), 1, (acc, x) => acc * x)
+            filter(range(0, 10), (x) => x > 6)
+            zip((1, 2, 3), (a, b))
+            length(range(0, 5))
+            filter((1, y, 3), (x) => x > 2)
+            map(x, square)
+            length(\"Δt\")
+            concat(\"ab\", \"c\")
+            concat((1, 2), (3,))
+            chars(\"hey\")
+            \"a\" == \"a\"
+            \"a\" != \"a\"
+            concat(\"a\", 1)
+            range(0, 1000000000000)
+            range(-1, 65536)");
+
+        let expected = ["(1, 4, 9)", "(2, 3, 4)", "10", "120", "(7, 8, 9)", "((1, a), (2, b))", "5", "filter((1, y, 3), (x) => x > 2)", "map(x, square)",
+            "2", "\"abc\"", "(1, 2, 3)", "(\"h\", \"e\", \"y\")", "1", "0", "concat(\"a\", 1)",
+            "range(0, 1000000000000)", "range(-1, 65536)"];
+        for (i, e) in expected.iter().enumerate() {
+            assert_eq!(script.get_expression(i).unwrap().to_string(), *e);
+        }
+    }
+
+}
@@ -11,6 +11,9 @@ pub mod algorithms;
 pub mod traversal;
 pub mod builtin;
 
+#[cfg(test)]
+mod testing;
+
 
 
 
@@ -1,11 +1,67 @@
 use std::{error::{self, Error}, fmt::{Display, Debug}};
 
+use super::Expression;
+
+
+/// Where in the source something went wrong, lines and columns start from 1 and columns count characters not bytes
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceSpan {
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+    pub source_line: String
+}
 
 #[derive(Debug)]
 pub enum DSLError{
     LexerError(String, Option<Box<dyn Error>>),
     ParserError(String, Option<Box<dyn Error>>),
-    RuntimeException
+    SyntaxError { message: String, span: SourceSpan, expected: Option<String> },
+    ImportError { message: String, span: SourceSpan, errors: Vec<DSLError> },
+    /// Something that can't be given a value while running, ie: dividing by zero
+    RuntimeException { message: String, expression: Expression }
+}
+
+impl SourceSpan {
+
+    /// start and end are byte offsets into input, spans covering multiple lines are cut off at the end of the first line
+    pub fn new(input: &str, start: usize, end: usize) -> Self {
+        let line_start = input[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let source_line = input[line_start..].lines().next().unwrap_or("").trim_end_matches('\r').to_string();
+        let text = input[start..end].lines().next().unwrap_or("").to_string();
+
+        SourceSpan {
+            line: input[..start].matches('\n').count() + 1,
+            column: input[line_start..start].chars().count() + 1,
+            text,
+            source_line
+        }
+    }
+
+    /// Moves a span made from input[offset..] so that it's relative to the whole of input instead
+    pub fn offset_by(self, input: &str, offset: usize) -> Self {
+        let line_start = input[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        // Only the first line of the remainder can start part way through a line of input
+        let (column, source_line) = if self.line == 1 {
+            let source_line = input[line_start..].lines().next().unwrap_or("").trim_end_matches('\r').to_string();
+            (self.column + input[line_start..offset].chars().count(), source_line)
+        } else {
+            (self.column, self.source_line)
+        };
+
+        SourceSpan { line: self.line + input[..offset].matches('\n').count(), column, text: self.text, source_line }
+    }
+
+}
+
+impl Display for SourceSpan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+        write!(f, "{} | {}{}", gutter, " ".repeat(self.column - 1), "^".repeat(self.text.chars().count().max(1)))
+    }
 }
 
 impl Error for DSLError {
@@ -13,7 +69,9 @@ impl Error for DSLError {
         match self {
             DSLError::LexerError(_, maybe_backtrace) => if let Some(e) = maybe_backtrace { Some(e.as_ref()) } else { None },
             DSLError::ParserError(_, maybe_backtrace) => if let Some(e) = maybe_backtrace { Some(e.as_ref()) } else { None },
-            DSLError::RuntimeException => None
+            DSLError::SyntaxError { .. } => None,
+            DSLError::ImportError { errors, .. } => if let Some(e) = errors.first() { Some(e) } else { None },
+            DSLError::RuntimeException { .. } => None
         }
     }
 
@@ -28,14 +86,33 @@ impl Display for DSLError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             DSLError::LexerError(msg, e) => {
-                writeln!(f, "Lexer Error: {}", msg)?;
-                e.fmt(f)
+                write!(f, "Lexer Error: {}", msg)?;
+                if let Some(e) = e { write!(f, "\n{}", e)?; }
+                Ok(())
             },
             DSLError::ParserError(msg, e) => {
-                writeln!(f, "Parser Error: {}", msg)?;
-                e.fmt(f)
+                write!(f, "Parser Error: {}", msg)?;
+                if let Some(e) = e { write!(f, "\n{}", e)?; }
+                Ok(())
             }
-            DSLError::RuntimeException => f.write_str("Genric Runtime Exception"),
+            DSLError::SyntaxError { message, span, expected } => {
+                writeln!(f, "Syntax Error: {} at line {}, column {}", message, span.line, span.column)?;
+                write!(f, "{}", span)?;
+                if let Some(expected) = expected {
+                    write!(f, "\n{} = expected {}", " ".repeat(span.line.to_string().len()), expected)?;
+                }
+                Ok(())
+            },
+            DSLError::ImportError { message, span, errors } => {
+                writeln!(f, "Import Error: {} at line {}, column {}", message, span.line, span.column)?;
+                write!(f, "{}", span)?;
+                // Errors from inside the module have spans relative to the module's own source
+                for e in errors {
+                    write!(f, "\n\n{}", e)?;
+                }
+                Ok(())
+            },
+            DSLError::RuntimeException { message, expression } => write!(f, "Runtime Error: {} in {}", message, expression.to_string()),
         }
     }
 }
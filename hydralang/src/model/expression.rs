@@ -1,8 +1,8 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, collections::HashSet};
 
 use crate::{traits::{ShallowEq, DeepEq}, parsing::parser::parse_statement};
 
-use super::{symbol_table::SymbolTable, error::DSLError, number::Number};
+use super::{symbol_table::SymbolTable, error::DSLError, number::Number, operator::{OperatorTable, Associativity}};
 
 /// A wrapper around Nodes, if you're doing something directly with Node types, consider thinking about how you could do it with this instead.
 #[derive(Debug, Clone)]
@@ -20,7 +20,11 @@ pub enum Node {
     Num(Number),
     Float(f64),
     Var(String),
+    Str(String),
     Vector(Vec<Node>),
+    Matrix { rows: usize, cols: usize, data: Vec<Node> }, // data is stored row by row
+    Lambda { params: Vec<String>, body: Box<Node> },
+    Equation(Box<Node>, Box<Node>),
     FunctionCall{ name: String, args: Vec<Node> }
 }
 
@@ -66,6 +70,12 @@ impl ToString for Expression {
     }
 }
 
+impl Expression {
+    pub fn to_string_with(&self, operators: &OperatorTable) -> String {
+        self.root_node.to_string_with(operators)
+    }
+}
+
 
 /*** Node Implementations ***/
 
@@ -78,32 +88,9 @@ impl PartialEq for Node {
 
 impl PartialOrd for Node {
 
-
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-
-        fn give_rank(node: &Node) -> i32 {
-            use Node::*;
-            match node {
-                Num(_) => 5,
-                Var(_) => 5,
-                Float(_) => 5,
-                Vector(_) => 5,
-                FunctionCall { name: _, args: _ } => 5,
-                Op(s, _, _) => {
-                    match s.as_str() {
-                        "+" => 1,
-                        "-" => 1,
-                        "*" => 2,
-                        "/" => 2,
-                        "^" => 3,
-                        _ => 4
-                    }
-                },
-                LOp(_, _) => 5
-            }
-        }
-
-        give_rank(self).partial_cmp(&give_rank(other))
+        let operators = OperatorTable::default();
+        self.precedence(&operators).partial_cmp(&other.precedence(&operators))
     }
 
 }
@@ -113,55 +100,149 @@ impl PartialOrd for Node {
 impl ToString for Node {
 
     fn to_string(&self) -> String {
+        self.to_string_with(&OperatorTable::default())
+    }
+}
+
+
+impl Node {
+
+    /// How tightly this node binds when printed, anything that isn't an operation can never be split apart so binds the tightest
+    pub fn precedence(&self, operators: &OperatorTable) -> usize {
+        match self {
+            Node::Op(s, _, _) => operators.infix_or_default(s).get_precedence(),
+            Node::LOp(s, _) => operators.prefix(s).map(|o| o.get_precedence()).unwrap_or(0),
+            // A negative number is printed with a leading "-", so it needs brackets in the same places a negation would
+            // Fractions are printed as a division, ie: "1/3"
+            Node::Num(Number::Rational(_, _)) => operators.infix_or_default("/").get_precedence(),
+            Node::Num(_) | Node::Float(_) if self.is_negative_number() => operators.prefix("-").map(|o| o.get_precedence()).unwrap_or(0),
+            Node::Lambda { .. } => 0, // The body of a lambda takes everything to its right
+            Node::Equation(_, _) => operators.infix_or_default("=").get_precedence(),
+            _ => usize::MAX
+        }
+    }
+
+    fn is_negative_number(&self) -> bool {
+        match self {
+            Node::Num(n) => *n < Number::Int(0),
+            Node::Float(f) => f.is_sign_negative(),
+            _ => false
+        }
+    }
+
+    // The operand printed at one end of this node, ignoring any brackets that might go around it
+    fn outermost_operand(&self, rightmost: bool) -> &Node {
+        match self {
+            Node::Op(_, box l, box r) | Node::Equation(box l, box r) => if rightmost { r.outermost_operand(rightmost) } else { l.outermost_operand(rightmost) },
+            Node::LOp(_, box child) if rightmost => child.outermost_operand(rightmost),
+            Node::Lambda { box body, .. } if rightmost => body.outermost_operand(rightmost),
+            _ => self
+        }
+    }
+
+    /// Whether n / d written out as number literals reads back as a fraction rather than a division, ie: "1/3" or "-4/2"
+    pub fn is_fraction_of(n: &Number, d: &Number) -> bool {
+        n.is_whole() && d.is_whole() && *d > Number::Int(0)
+    }
+
+    // Whether l / r would be printed in a way that reads back as a Leibniz derivative ie: "dy / dx", "d(y) / dx" or "d / dx(y)"
+    fn looks_like_leibniz(l: &Node, r: &Node) -> bool {
+        let numerator = match l.outermost_operand(true) {
+            Node::Var(name) | Node::FunctionCall { name, .. } => name.starts_with('d'),
+            _ => false
+        };
+        let denominator = match r.outermost_operand(false) {
+            Node::Var(name) | Node::FunctionCall { name, .. } => name.starts_with('d') && name.len() > 1,
+            _ => false
+        };
+
+        numerator && denominator
+    }
+
+    /// Prints the node so that parsing the result gives back the same tree, with the exception that a Float reads back as a Num of the same value.
+    /// Floats that aren't finite are a known exception, there's no number that can be written for them so NaN, inf and -inf read back as variables.
+    /// Nothing parsed or evaluated makes a Float so this only comes up for trees built by hand
+    pub fn to_string_with(&self, operators: &OperatorTable) -> String {
         use Node::*;
 
-        fn wrap_if_lower(parent: &Node, child: &Node) -> String {
-            if *child < *parent {
-                format!("({})", child.to_string())
+        // A child of equal precedence only needs brackets when it sits on the side its parent doesn't associate towards ie: "a - (b - c)" or "(a^b)^c"
+        fn wrap_if_lower(child: &Node, precedence: usize, wrap_equal: bool, operators: &OperatorTable) -> String {
+            let child_precedence = child.precedence(operators);
+            if child_precedence < precedence || (wrap_equal && child_precedence == precedence) {
+                format!("({})", child.to_string_with(operators))
             }else{
-                format!("{}", child.to_string())
+                child.to_string_with(operators)
             }
         }
 
-
-
         match &self {
 
             Op(s, box a, box b) => {
-                if ["+", "-", "*", "/"].contains(&s.as_str()) {
-                    format!("{} {} {}", wrap_if_lower(self, a), s, wrap_if_lower(self, b))
+                let precedence = self.precedence(operators);
+                let associativity = operators.infix_or_default(s).get_associativity();
+                let left = if s == "/" && (Self::looks_like_leibniz(a, b) || matches!((a, b), (Num(n), Num(d)) if Self::is_fraction_of(n, d))) {
+                    format!("({})", a.to_string_with(operators))
+                } else {
+                    wrap_if_lower(a, precedence, associativity == Associativity::Right, operators)
+                };
+                let right = wrap_if_lower(b, precedence, associativity == Associativity::Left, operators);
+
+                if s == "^" {
+                    format!("{}{}{}", left, s, right)
                 }else {
-                    format!("{}{}{}", wrap_if_lower(self, a), s, wrap_if_lower(self, b))
+                    format!("{} {} {}", left, s, right)
                 }
             },
-            LOp(op, box a) => format!("${op}{}", wrap_if_lower(self, a)),
+            // "-3" reads back as the number -3, so negating a number needs brackets to stay a negation
+            LOp(op, box a @ (Num(_) | Float(_))) if op == "-" => format!("{op}({})", a.to_string_with(operators)),
+            LOp(op, box a) => format!("{op}{}", wrap_if_lower(a, self.precedence(operators), false, operators)),
             Num(a) => format!("{}", a),
-            Float(a) => format!("{}", a),
+            Float(a) => format!("{:?}", a), // Debug is the shortest form that reads back as the same float
             Var(a) => format!("{}", a),
-            Vector(v) => {
-                if v.len() == 0 {
-                    return "()".to_string();
-                }
-                let mut interspersed = Vec::new();
-                let mut iter = v.iter();
-                interspersed.push(iter.next().unwrap().to_string());
-
-                while let Some(e) = iter.next() {
-                    interspersed.push(", ".to_string());
-                    interspersed.push(e.to_string());
-                }
-
-                format!("({})", interspersed.iter().map(|s| s.clone()).collect::<String>())
-            }, 
+            Str(s) => format!("\"{}\"", s.chars().map(|c| match c {
+                '"' => "\\\"".to_string(),
+                '\\' => "\\\\".to_string(),
+                '\n' => "\\n".to_string(),
+                '\t' => "\\t".to_string(),
+                c => c.to_string()
+            }).collect::<String>()),
+            Vector(v) if v.len() == 1 => format!("({},)", v[0].to_string_with(operators)),
+            Vector(v) => format!("({})", v.iter().map(|node| node.to_string_with(operators)).collect::<Vec<String>>().join(", ")),
+            Matrix { cols, data, .. } => {
+                // One row per line with the columns lined up
+                let cells = data.iter().map(|node| node.to_string_with(operators)).collect::<Vec<String>>();
+                let widths = (0..*cols).map(|c| cells.iter().skip(c).step_by(*cols).map(|s| s.chars().count()).max().unwrap_or(0)).collect::<Vec<usize>>();
+                let rows = cells.chunks(*cols).map(|row| {
+                    row.iter().zip(widths.iter()).map(|(cell, width)| format!("{:>width$}", cell, width = width)).collect::<Vec<String>>().join(", ")
+                }).collect::<Vec<String>>();
+
+                format!("[{}]", rows.join("\n "))
+            },
+            Lambda { params, box body } => format!("({}) => {}", params.join(", "), body.to_string_with(operators)),
+            // Equations don't chain so another equation on either side needs brackets, a lambda on the right already stops at the end
+            Equation(box l, box r) => {
+                let precedence = self.precedence(operators);
+                format!("{} = {}", wrap_if_lower(l, precedence, true, operators), wrap_if_lower(r, precedence, matches!(r, Equation(_, _)), operators))
+            },
+            // Lambdas can't go between bars without brackets, so they're left as a call
+            FunctionCall { name, args } if name == "abs" && args.len() == 1 && !matches!(args[0], Lambda { .. }) => format!("|{}|", args[0].to_string_with(operators)),
             FunctionCall { name, args } => {
-                format!("{}({})", name, args.iter().map(|node| node.to_string()).collect::<Vec<String>>().join(", "))
+                format!("{}({})", name, args.iter().map(|node| node.to_string_with(operators)).collect::<Vec<String>>().join(", "))
             },
         }
     }
-}
-
 
-impl Node {
+    /// Variables that aren't bound by a lambda somewhere above them
+    pub fn free_variables(&self) -> HashSet<String> {
+        match self {
+            Node::Var(name) => HashSet::from([name.clone()]),
+            Node::Op(_, box l, box r) | Node::Equation(box l, box r) => l.free_variables().union(&r.free_variables()).cloned().collect(),
+            Node::LOp(_, box child) => child.free_variables(),
+            Node::Vector(nodes) | Node::Matrix { data: nodes, .. } | Node::FunctionCall { args: nodes, .. } => nodes.iter().flat_map(|n| n.free_variables()).collect(),
+            Node::Lambda { params, box body } => body.free_variables().into_iter().filter(|v| !params.contains(v)).collect(),
+            Node::Num(_) | Node::Float(_) | Node::Str(_) => HashSet::new()
+        }
+    }
 
     pub fn is_op(&self) -> bool {
         match self {
@@ -197,6 +278,7 @@ impl Node {
             },
             (Node::Num(a_n), Node::Num(b_n)) => a_n == b_n,
             (Node::Float(a_n), Node::Float(b_n)) => a_n == b_n,
+            (Node::Str(a), Node::Str(b)) => a == b,
             (Node::Var(a), Node::Var(b)) => {
                 if let Some(previous) = symbol_lookup.get(a).clone() {
                     match previous.get_root_node() {
@@ -226,6 +308,15 @@ impl Node {
             (Node::Vector(v1), Node::Vector(v2)) => {
                 v1.len() == v2.len() && v1.iter().zip(v2.iter()).all(|(a, b)| a.compare_to(b, symbol_lookup))
             }
+            (Node::Matrix { rows, cols, data }, Node::Matrix { rows: rows2, cols: cols2, data: data2 }) => {
+                rows == rows2 && cols == cols2 && data.iter().zip(data2.iter()).all(|(a, b)| a.compare_to(b, symbol_lookup))
+            }
+            (Node::Lambda { .. }, Node::Lambda { .. }) => self.deep_eq(b),
+            (Node::Equation(a_l, a_r), Node::Equation(b_l, b_r)) => {
+                let l_eq = a_l.compare_to(b_l, symbol_lookup);
+                let r_eq = a_r.compare_to(b_r, symbol_lookup);
+                l_eq && r_eq
+            },
             (Node::FunctionCall { name, args }, Node::FunctionCall { name: name2, args: args2 }) => {
                 name == name2 && args.len() == args2.len() && args.iter().zip(args2.iter()).all(|(a, b)| a.compare_to(b, symbol_lookup))
             }
@@ -40,6 +40,11 @@ pub fn num(n: i64) -> Node {
     Node::Num(Number::new(n))
 }
 
+/// The number n/d in lowest terms, which is only a Rational when d doesn't divide n
+pub fn fraction(n: i64, d: i64) -> Node {
+    Node::Num(Number::new(n).checked_div(&Number::new(d)).expect("Fractions can't have a denominator of zero"))
+}
+
 pub fn float(n: f64) -> Node {
     Node::Float(n)
 }
@@ -48,10 +53,26 @@ pub fn var(s: String) -> Node {
     Node::Var(s)
 }
 
+pub fn string(s: &str) -> Node {
+    Node::Str(s.to_string())
+}
+
 pub fn vector(v: Vec<Node>) -> Node {
     Node::Vector(v)
 }
 
+pub fn matrix(rows: usize, cols: usize, data: Vec<Node>) -> Node {
+    Node::Matrix { rows, cols, data }
+}
+
+pub fn lambda(params: &[&str], body: Node) -> Node {
+    Node::Lambda { params: params.iter().map(|p| p.to_string()).collect(), body: Box::new(body) }
+}
+
+pub fn equation(l: Node, r: Node) -> Node {
+    Node::Equation(Box::new(l), Box::new(r))
+}
+
 pub fn vec3(x: i64, y: i64, z: i64) -> Node {
     vector(vec![num(x), num(y), num(z)])
 }
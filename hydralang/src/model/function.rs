@@ -2,7 +2,7 @@ use std::rc::Rc;
 
 use crate::{traits::{Callable, DeepEq}, visitor::{VariableReplacer, ExpressionModfierVisitor, DefaultSimplifyVisitor}};
 use crate::model::expression_builder::num;
-use super::{Expression, expression::Node, symbol_table::SymbolTable, Script};
+use super::{Expression, expression::Node, symbol_table::SymbolTable, Script, operator::OperatorTable};
 
 
 
@@ -21,24 +21,30 @@ impl ToString for ExpressionTemplate {
 
 impl Callable for ExpressionTemplate {
 
-    fn call(&self, symbol_table: SymbolTable) -> Expression {
-        VariableReplacer::new(symbol_table).visit(self.expr.clone())
+    fn call(&self, symbol_table: SymbolTable, script: &Script) -> Expression {
+        VariableReplacer::new(symbol_table).with_script(script).visit(self.expr.clone())
     }
+
+    fn to_string_with(&self, operators: &OperatorTable) -> String { self.expr.to_string_with(operators) }
 }
 
 
+/// Internal functions are given the script they're called from so they can call back into it, ie: to apply a function they were passed.
+/// Returning the call unchanged means the function has nothing to do with these arguments
+pub type InternalFunction = fn(&[Node], &Script) -> Expression;
+
 pub struct RustInternalFunction {
     args: Box<[String]>,
-    internal_function: fn(&[Node]) -> Expression
+    internal_function: InternalFunction
 }
 
 impl RustInternalFunction {
-    pub fn new(args: Box<[String]>, f: fn(&[Node]) -> Expression) -> Self { Self { args, internal_function: f } }
+    pub fn new(args: Box<[String]>, f: InternalFunction) -> Self { Self { args, internal_function: f } }
 }
 
 impl Callable for RustInternalFunction {
-    fn call(&self, symbol_table: SymbolTable) -> Expression { 
-        (self.internal_function)(symbol_table.get_args_nodes(self.args.as_ref()).unwrap().as_ref()) 
+    fn call(&self, symbol_table: SymbolTable, script: &Script) -> Expression { 
+        (self.internal_function)(symbol_table.get_args_nodes(self.args.as_ref()).unwrap().as_ref(), script) 
     }
 }
 
@@ -69,20 +75,7 @@ pub struct FunctionCollection {
 
 impl ToString for FunctionDef {
     fn to_string(&self) -> String {
-        let mut result = String::new();
-
-        result.push_str(format!("{}(", self.name).as_str());
-        result.push_str(self.args.iter().map(|s| s.to_string()).collect::<Vec<String>>().join(", ").as_str());
-        result.push_str(") = ");
-        result.push_str(self.expr.to_string().as_str());
-
-        if self.constraints.len() != 0 {
-            result.push_str(" where { ");
-            result.push_str(self.constraints.iter().map(|s| s.to_string()).collect::<Vec<String>>().join(", ").as_str());
-            result.push_str(" }");
-        }
-
-        result
+        self.to_string_with(&OperatorTable::default())
     }
 }
 
@@ -100,6 +93,23 @@ impl FunctionDef {
         &self.name
     }
 
+    pub fn to_string_with(&self, operators: &OperatorTable) -> String {
+        let mut result = String::new();
+
+        result.push_str(format!("{}(", self.name).as_str());
+        result.push_str(self.args.iter().map(|s| s.to_string_with(operators)).collect::<Vec<String>>().join(", ").as_str());
+        result.push_str(") = ");
+        result.push_str(self.expr.to_string_with(operators).as_str());
+
+        if self.constraints.len() != 0 {
+            result.push_str(" where { ");
+            result.push_str(self.constraints.iter().map(|s| s.to_string_with(operators)).collect::<Vec<String>>().join(", ").as_str());
+            result.push_str(" }");
+        }
+
+        result
+    }
+
     pub fn get_args(&self) -> Vec<Expression> {
         self.args.clone()
     }
@@ -127,14 +137,14 @@ impl FunctionDef {
         // Check if contraints are valid
         let mut constraint_eval = self.constraints.iter()
             .map(|e| ExpressionTemplate::new(e.clone()))
-            .map(|t| t.call(symbol_table.clone()))
+            .map(|t| t.call(symbol_table.clone(), script))
             .map(|e| DefaultSimplifyVisitor::new(script).visit(e));
 
         if !(constraint_eval.all(|e| e.deep_eq(&Expression::new(num(1))))) {
             return None;
         }
 
-        Some(self.expr.call(symbol_table))
+        Some(self.expr.call(symbol_table, script))
 
     }
 
@@ -151,6 +161,19 @@ impl FunctionCollection {
 
     pub fn get_function_defs(&self) -> Vec<FunctionDef> { self.function_defs.clone() }
 
+    pub fn to_string_with(&self, operators: &OperatorTable) -> String {
+        let mut result = String::new();
+
+        for func in &self.function_defs {
+            if !func.is_system_function {
+                result.push_str(func.to_string_with(operators).as_str());
+                result.push_str("\n");
+            }
+        }
+
+        result
+    }
+
     pub fn hide(&mut self) {
         for func in self.function_defs.iter_mut() {
             func.hide();
@@ -182,15 +205,6 @@ impl FunctionCollection {
 
 impl ToString for FunctionCollection {
     fn to_string(&self) -> String {
-        let mut result = String::new();
-
-        for func in &self.function_defs {
-            if !func.is_system_function {
-                result.push_str(func.to_string().as_str());
-                result.push_str("\n");
-            }
-        }
-
-        result
+        self.to_string_with(&OperatorTable::default())
     }
 }
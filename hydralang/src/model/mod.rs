@@ -4,29 +4,46 @@ pub mod function;
 pub mod symbol_table;
 pub mod error;
 pub mod number;
+pub mod operator;
 
 pub use expression::Expression;
-pub use script::Script;
+pub use script::{Script, Statement};
 
 pub mod script {
     use std::collections::HashMap;
 
-    use crate::{parsing::parser::parse_script, visitor::{DefaultSimplifyVisitor, ExpressionModfierVisitor}};
+    use crate::{parsing::{parser::{parse_script, parse_script_with_operators, parse_script_recovering, parse_script_with_loader}, loader::ModuleLoader}, visitor::{DefaultSimplifyVisitor, ExpressionModfierVisitor, HistoryReplacer, VariableReplacer}};
 
-    use super::{function::{FunctionCollection, FunctionDef}, Expression, error::DSLError};
+    use super::{function::{FunctionCollection, FunctionDef}, Expression, expression::Node, error::DSLError, operator::OperatorTable, symbol_table::SymbolTable};
 
 
+    /// A line of a script that isn't a definition. Assignments and unsets only affect the lines after them
+    #[derive(Clone)]
+    pub enum Statement {
+        Evaluate(Expression),
+        Assign(String, Expression),
+        Unset(String),
+        /// A line starting with an operator, ie: "/ 2", which is read as "ans / 2". It is applied to both sides of the previous result when that is an equation,
+        /// otherwise a line that reads as an expression by itself (like "-3") is just that and anything else carries on from the previous result
+        Continue { expression: Expression, written: Option<Expression> }
+    }
+
     #[derive(Clone)]
     pub struct Script {
         function_defs: HashMap<String, FunctionCollection>,
-        expressions: Vec<Expression>
+        statements: Vec<Statement>, // As they were written, every run starts again from these
+        results: Vec<Statement>, // What the last run made of each statement, shown in place of the statement
+        bindings: SymbolTable, // Values given to variables by assignments, as of the line currently being run
+        history: Vec<Expression>, // Results of the expressions evaluated so far, these are what "ans" and "ans(n)" refer to
+        operators: OperatorTable
     }
 
 
     impl Script {
 
         pub fn new(function_defs: Vec<FunctionDef>, expressions: Vec<Expression>) -> Self {
-            let mut script = Script { function_defs: HashMap::new(), expressions };
+            let statements = expressions.into_iter().map(Statement::Evaluate).collect();
+            let mut script = Script { function_defs: HashMap::new(), statements, results: Vec::new(), bindings: SymbolTable::new(), history: Vec::new(), operators: OperatorTable::default() };
 
             for function_def in function_defs {
                 script.add_function_def(function_def);
@@ -65,18 +82,63 @@ pub mod script {
             result
         }
 
+        /// The index-th expression evaluated by the script, assignments aren't counted
         pub fn get_expression(&self, index: usize) -> Option<&Expression> {
-            self.expressions.get(index)
+            self.shown_statements().filter_map(|s| if let Statement::Evaluate(e) = s { Some(e) } else { None }).nth(index)
+        }
+
+        // The result of each statement that has been run, followed by the statements that haven't as they were written
+        fn shown_statements(&self) -> impl Iterator<Item = &Statement> {
+            self.results.iter().chain(self.statements.iter().skip(self.results.len()))
         }
 
         pub fn add_expression_evaluation(&mut self, expression: Expression) {
-            self.expressions.push(expression);
+            self.statements.push(Statement::Evaluate(expression));
+        }
+
+        pub fn add_statement(&mut self, statement: Statement) {
+            self.statements.push(statement);
+        }
+
+        pub fn get_bindings(&self) -> &SymbolTable {
+            &self.bindings
+        }
+
+        pub fn get_history(&self) -> &Vec<Expression> {
+            &self.history
+        }
+
+        pub fn get_operators(&self) -> &OperatorTable {
+            &self.operators
+        }
+
+        pub fn set_operators(&mut self, operators: OperatorTable) {
+            self.operators = operators;
         }
 
         pub fn parse(input: &str) -> Result<Self, DSLError> {
             parse_script(input)
         }
 
+        /// Parses input as a continuation of this script, so any operators declared here are understood by the input
+        pub fn parse_continuation(&self, input: &str) -> Result<Self, DSLError> {
+            parse_script_with_operators(input, &self.operators)
+        }
+
+        /// Same as parse_continuation except that lines which fail to parse are skipped and reported rather than failing the whole input
+        pub fn parse_continuation_recovering(&self, input: &str) -> (Self, Vec<DSLError>) {
+            parse_script_recovering(input, &self.operators)
+        }
+
+        /// Same as parse_continuation_recovering, imports are found using loader which remembers what it has already imported
+        pub fn parse_continuation_with_loader(&self, input: &str, loader: &mut ModuleLoader) -> (Self, Vec<DSLError>) {
+            parse_script_with_loader(input, &self.operators, loader)
+        }
+
+        pub fn has_function(&self, name: &str) -> bool {
+            self.function_defs.contains_key(name)
+        }
+
         pub fn exec_function(&self, name: &str, args: Vec<Expression>) -> Option<Expression> {
             self.function_defs.get(&name.to_string())?.try_apply(&args, &self)
         }
@@ -85,15 +147,73 @@ pub mod script {
             for f in other.get_function_defs() {
                 self.add_function_def(f); // TODO: Figure out when functions should be overridden vs adjacent, for now newly added functions can never replace old ones
             }
-            self.expressions.append(&mut other.expressions.clone())
+            self.statements.append(&mut other.statements.clone());
+            self.operators.merge(&other.operators);
+        }
+
+        // Bindings and history are built up again from the top every run so each line only ever sees what came before it.
+        // Running stops at the first line that can't be evaluated, that line and everything after it are left as they were
+        pub fn run(&mut self) -> Result<(), DSLError> {
+            self.bindings = SymbolTable::new();
+            self.history = Vec::new();
+            self.results = Vec::new();
+
+            for line in 0..self.statements.len() {
+                let new_statement = match self.statements[line].clone() {
+                    Statement::Evaluate(e) => Statement::Evaluate(self.evaluate(e)?),
+                    Statement::Continue { expression, written } => {
+                        let operate = |side: &Node| {
+                            let mut symbol_table = SymbolTable::new();
+                            symbol_table.insert("ans".to_string(), Expression::new(side.clone()));
+                            VariableReplacer::new(symbol_table).visit_node(expression.get_root_node().clone())
+                        };
+
+                        // Otherwise "ans" is the previous result, which is filled in the same as any other line
+                        let e = match (self.history.last().map(|e| e.get_root_node()), written) {
+                            (Some(Node::Equation(box l, box r)), _) => Node::Equation(Box::new(operate(l)), Box::new(operate(r))),
+                            (_, Some(written)) => written.get_root_node().clone(),
+                            (_, None) => expression.get_root_node().clone()
+                        };
+
+                        // Once run it's no different to having typed out the whole expression
+                        Statement::Evaluate(self.evaluate(Expression::new(e))?)
+                    },
+                    Statement::Assign(name, e) => {
+                        let e = HistoryReplacer::new(&self.history).visit(e);
+                        let mut visitor = DefaultSimplifyVisitor::new(self);
+                        let value = visitor.visit(e);
+                        if let Some(error) = visitor.take_error() {
+                            return Err(error);
+                        }
+
+                        // Something like "x = x + 1" with no value for x can't be substituted, it would never stop growing
+                        if value.get_root_node().free_variables().contains(&name) {
+                            self.bindings.remove(&name);
+                        } else {
+                            self.bindings.insert(name.clone(), value.clone());
+                        }
+                        Statement::Assign(name, value)
+                    },
+                    Statement::Unset(name) => {
+                        self.bindings.remove(&name);
+                        Statement::Unset(name)
+                    }
+                };
+                self.results.push(new_statement);
+            }
+
+            Ok(())
         }
 
-        pub fn run(&mut self) {
-            for line in 0..self.expressions.len() {
-                let mut simplify = DefaultSimplifyVisitor::new(&self); //TODO: Fix dumb clone
-                let new_expr = simplify.visit(self.expressions.get(line).unwrap().clone());
-                self.expressions[line] = new_expr;
+        fn evaluate(&mut self, e: Expression) -> Result<Expression, DSLError> {
+            let e = HistoryReplacer::new(&self.history).visit(e);
+            let mut visitor = DefaultSimplifyVisitor::new(self);
+            let result = visitor.visit(e);
+            if let Some(error) = visitor.take_error() {
+                return Err(error);
             }
+            self.history.push(result.clone());
+            Ok(result)
         }
 
     }
@@ -106,14 +226,24 @@ pub mod script {
             let mut result = String::new();
     
             for func_collection in self.function_defs.values() {
-                if func_collection.to_string().as_str().trim() != "" {
-                    result.push_str(func_collection.to_string().as_str());
+                let func_collection = func_collection.to_string_with(&self.operators);
+                if func_collection.as_str().trim() != "" {
+                    result.push_str(func_collection.as_str());
                     result.push_str("\n");
                 }
             }
     
-            for expr in &self.expressions {
-                result.push_str(expr.to_string().as_str());
+            for statement in self.shown_statements() {
+                match statement {
+                    Statement::Evaluate(e) => result.push_str(e.to_string_with(&self.operators).as_str()),
+                    Statement::Assign(name, e) => result.push_str(format!("{} = {}", name, e.to_string_with(&self.operators)).as_str()),
+                    Statement::Unset(name) => result.push_str(format!("unset({})", name).as_str()),
+                    // "ans" is always the leftmost operand so it's printed first, without it the line reads back the same
+                    Statement::Continue { expression, .. } => {
+                        let printed = expression.to_string_with(&self.operators);
+                        result.push_str(printed.strip_prefix("ans").unwrap_or(&printed).trim_start())
+                    }
+                }
                 result.push_str("\n");
             }
     
@@ -1,34 +1,225 @@
+use std::cmp::Ordering;
 use std::fmt::Display;
 use std::str::FromStr;
-use std::ops::{Add, Sub, Mul, Div};
+use std::ops::{Add, Sub, Mul, Neg};
 
 use bigdecimal::BigDecimal;
-use num_traits::Pow;
+use num_bigint::{BigInt, Sign};
+use num_traits::{One, Pow, ToPrimitive, Zero};
 
+use crate::algorithms::gcd;
+use super::error::DSLError;
+
+// Powers with more bits than this are left alone rather than taking forever to work out, it's a little over a million digits
+const MAX_POWER_BITS: u64 = 1 << 22;
+
+/// Whole numbers are kept as an Int for as long as they fit, anything that doesn't is a BigInt. Results are always moved back to an Int when they fit again.
+/// Dividing whole numbers gives an exact Rational, which is kept in lowest terms with a positive denominator greater than 1
 #[derive(Clone, Debug)]
 pub enum Number {
     Int(i64),
+    BigInt(Box<BigInt>),
+    Rational(Box<BigInt>, Box<BigInt>),
     Decimal(Box<BigDecimal>)
 }
 
 
 impl Number {
 
-    pub fn parse(input: &str) -> Self {
-        if input.contains('.') {
-            Number::Decimal(Box::new(BigDecimal::from_str(input).unwrap()))
+    /// Reads a number literal as it is written in a script, ie: "42", "1.5", "6.022e23", "0xFF", "0b1010", "1_000_000" or "50 000".
+    /// Anything written with a decimal point is a Decimal, everything else is an Int unless it's too big or has a fractional part
+    pub fn parse(input: &str) -> Result<Self, DSLError> {
+        let invalid = || DSLError::ParserError(format!("Invalid number \"{}\"", input), None);
+        let digits = input.chars().filter(|c| *c != '_' && *c != ' ').collect::<String>();
+
+        let radix = match digits.get(..2) {
+            Some("0x") | Some("0X") => Some(16),
+            Some("0b") | Some("0B") => Some(2),
+            _ => None
+        };
+
+        if let Some(radix) = radix {
+            return BigInt::parse_bytes(&digits.as_bytes()[2..], radix).map(Number::from_big_int).ok_or_else(invalid);
+        }
+
+        if let Ok(n) = digits.parse() {
+            return Ok(Number::Int(n));
+        }
+
+        let decimal = BigDecimal::from_str(&digits).map_err(|_| invalid())?;
+        if !digits.contains('.') && !Number::too_long_to_write_out(&decimal) && decimal.is_integer() {
+            Ok(Number::from_big_int(decimal.with_scale(0).into_bigint_and_exponent().0))
         } else {
-            match input.parse() {
-                Ok(n) => Number::Int(n),
-                Err(_) => Number::Decimal(Box::new(BigDecimal::from_str(input).unwrap()))
-            }
+            Ok(Number::Decimal(Box::new(decimal)))
         }
     }
 
+    // Writing out something like 1e100000000 or 1e-100000000 in full would take forever, so past the same limit as powers it stays a Decimal and is printed with an exponent
+    fn too_long_to_write_out(decimal: &BigDecimal) -> bool {
+        let (mantissa, scale) = decimal.as_bigint_and_exponent();
+        mantissa.bits().saturating_add(scale.unsigned_abs().saturating_mul(4)) > MAX_POWER_BITS
+    }
+
     pub fn new(input: i64) -> Self {
         Self::Int(input)
     }
 
+    /// An Int if it fits, otherwise a BigInt
+    pub fn from_big_int(n: BigInt) -> Self {
+        match n.to_i64() {
+            Some(n) => Number::Int(n),
+            None => Number::BigInt(Box::new(n))
+        }
+    }
+
+    /// numerator / denominator in lowest terms, which is a whole number if the denominator divides the numerator. None if the denominator is zero
+    pub fn from_ratio(numerator: BigInt, denominator: BigInt) -> Option<Self> {
+        if denominator.is_zero() {
+            return None;
+        }
+
+        let divisor = gcd(&numerator, &denominator);
+        let (numerator, denominator) = if denominator < BigInt::zero() { (-numerator / &divisor, -denominator / &divisor) } else { (numerator / &divisor, denominator / &divisor) };
+
+        if denominator.is_one() {
+            Some(Number::from_big_int(numerator))
+        } else {
+            Some(Number::Rational(Box::new(numerator), Box::new(denominator)))
+        }
+    }
+
+    // Sums and products of ratios are over a product of denominators, which can't be zero
+    fn over_denominators(numerator: BigInt, denominator: BigInt) -> Self {
+        Number::from_ratio(numerator, denominator).expect("Denominators are never zero")
+    }
+
+    /// None when dividing by zero. There's no "/" for numbers so that every division has to say what happens then
+    pub fn checked_div(&self, rhs: &Number) -> Option<Number> {
+        if rhs.is_zero() {
+            return None;
+        }
+
+        match (self, rhs) {
+            (Number::Int(a), Number::Int(b)) => match a.checked_rem(*b) {
+                Some(0) => Some(Number::Int(a / b)),
+                _ => Number::from_ratio(BigInt::from(*a), BigInt::from(*b))
+            },
+            (Number::Int(a), Number::Decimal(box b)) => Some(Number::Decimal(Box::new(BigDecimal::from(*a) / b))),
+            (Number::Decimal(box a), Number::Int(b)) => Some(Number::Decimal(Box::new(a / BigDecimal::from(*b)))),
+            (Number::Decimal(box a), Number::Decimal(box b)) => Some(Number::Decimal(Box::new(a / b))),
+            (a, b) => match a.exact_pair(b) {
+                Some(((an, ad), (bn, bd))) => Number::from_ratio(an * bd, ad * bn),
+                None => Some(Number::Decimal(Box::new(a.to_big_decimal() / b.to_big_decimal())))
+            }
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::Int(n) => *n == 0,
+            Number::Decimal(box d) => d.is_zero(),
+            // BigInts and Rationals are never zero since they'd have been an Int
+            _ => false
+        }
+    }
+
+    /// Whether this is a whole number with no decimal part written, ie: an Int or a BigInt
+    pub fn is_whole(&self) -> bool {
+        matches!(self, Number::Int(_) | Number::BigInt(_))
+    }
+
+    /// The exact value as numerator and positive denominator in lowest terms, decimals included since they're a whole number over some power of ten.
+    /// None for a decimal whose power of ten is too long to write out, ie: 1e999999999 or 1e-999999999
+    fn to_ratio(&self) -> Option<(BigInt, BigInt)> {
+        match self {
+            Number::Int(n) => Some((BigInt::from(*n), BigInt::one())),
+            Number::BigInt(box n) => Some((n.clone(), BigInt::one())),
+            Number::Rational(box n, box d) => Some((n.clone(), d.clone())),
+            Number::Decimal(box d) => {
+                let (digits, scale) = d.as_bigint_and_exponent();
+                if Number::too_long_to_write_out(d) {
+                    None
+                } else if scale < 0 {
+                    Some((digits * BigInt::from(10).pow(-scale as u32), BigInt::one()))
+                } else {
+                    let denominator = BigInt::from(10).pow(scale as u32);
+                    let divisor = gcd(&digits, &denominator);
+                    Some((digits / &divisor, denominator / divisor))
+                }
+            }
+        }
+    }
+
+    fn to_big_decimal(&self) -> BigDecimal {
+        match self {
+            Number::Int(n) => BigDecimal::from(*n),
+            Number::BigInt(box n) => BigDecimal::new(n.clone(), 0),
+            Number::Rational(box n, box d) => BigDecimal::new(n.clone(), 0) / BigDecimal::new(d.clone(), 0),
+            Number::Decimal(box d) => d.clone()
+        }
+    }
+
+    // Both sides as exact ratios, None if either is a Decimal since arithmetic with a decimal should give a decimal back
+    fn exact_pair(&self, other: &Number) -> Option<((BigInt, BigInt), (BigInt, BigInt))> {
+        match (self, other) {
+            (Number::Decimal(_), _) | (_, Number::Decimal(_)) => None,
+            _ => Some((self.to_ratio()?, other.to_ratio()?))
+        }
+    }
+
+    /// The largest number that both numbers are a whole multiple of, ie: gcd(12, 18) = 6 and gcd(1/2, 1/3) = 1/6.
+    /// None when either is a decimal too long to write out
+    pub fn gcd(&self, other: &Number) -> Option<Number> {
+        let ((an, ad), (bn, bd)) = (self.to_ratio()?, other.to_ratio()?);
+        Some(Number::over_denominators(gcd(&(an * &bd), &(bn * &ad)), ad * bd))
+    }
+
+    pub fn abs(self) -> Self {
+        if self < Number::Int(0) { -self } else { self }
+    }
+
+    /// None when the result can't be written down exactly, ie: 2^(1/2), (-4)^(1/2) and 0^(-1).
+    /// Fractional powers are roots, so 4^(1/2) is 2 and 8^(2/3) is 4. When either side is a decimal the result is too
+    pub fn pow(&self, exponent: &Number) -> Option<Number> {
+        if let (Number::Int(a), Number::Int(b)) = (self, exponent) {
+            if let Some(r) = u32::try_from(*b).ok().and_then(|b| a.checked_pow(b)) {
+                return Some(Number::Int(r));
+            }
+        }
+
+        let (exponent_n, exponent_d) = exponent.to_ratio()?;
+        let (base_n, base_d) = self.to_ratio()?;
+
+        if base_n.is_zero() {
+            return match exponent_n.sign() {
+                Sign::Plus => Some(Number::Int(0)),
+                Sign::NoSign => Some(Number::Int(1)),
+                Sign::Minus => None
+            };
+        }
+
+        // Even roots of negative numbers aren't real, odd ones keep the sign ie: (-8)^(1/3) = -2
+        let root = exponent_d.to_u32()?;
+        let exact_root = |n: &BigInt| Some(n.nth_root(root)).filter(|r| r.pow(root) == *n);
+        if base_n < BigInt::zero() && root % 2 == 0 {
+            return None;
+        }
+        let (root_n, root_d) = (exact_root(&base_n)?, exact_root(&base_d)?);
+
+        let power = exponent_n.magnitude().to_u32()?;
+        if (root_n.bits() + root_d.bits()) * power as u64 > MAX_POWER_BITS {
+            return None;
+        }
+
+        let (n, d) = (root_n.pow(power), root_d.pow(power));
+        let result = if exponent_n < BigInt::zero() { Number::from_ratio(d, n)? } else { Number::from_ratio(n, d)? };
+
+        match (self, exponent) {
+            (Number::Decimal(_), _) | (_, Number::Decimal(_)) => Some(Number::Decimal(Box::new(result.to_big_decimal()))),
+            _ => Some(result)
+        }
+    }
+
 }
 
 
@@ -39,33 +230,43 @@ impl PartialEq<Number> for Number {
             (Self::Int(l0), Self::Int(r0)) => l0 == r0,
             (Self::Decimal(l0), Self::Decimal(r0)) => l0 == r0,
             (Self::Int(l0), Self::Decimal(box r0)) => BigDecimal::from(*l0) == *r0,
-            (Self::Decimal(box l0), Self::Int(r0)) => *l0 == BigDecimal::from(*r0)
+            (Self::Decimal(box l0), Self::Int(r0)) => *l0 == BigDecimal::from(*r0),
+            // Denominators are positive so cross multiplying compares the values exactly
+            (l, r) => match (l.to_ratio(), r.to_ratio()) {
+                (Some((ln, ld)), Some((rn, rd))) => ln * rd == rn * ld,
+                _ => l.to_big_decimal() == r.to_big_decimal()
+            }
         }
     }
 }
 
-impl Display for Number {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Number::Int(n) => write!(f, "{}", n),
-            Number::Decimal(box d) => write!(f, "{}", d.to_string()),
+impl PartialOrd<Number> for Number {
+
+    fn partial_cmp(&self, other: &Number) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Int(l0), Self::Int(r0)) => l0.partial_cmp(r0),
+            (Self::Decimal(l0), Self::Decimal(r0)) => l0.partial_cmp(r0),
+            (Self::Int(l0), Self::Decimal(box r0)) => BigDecimal::from(*l0).partial_cmp(r0),
+            (Self::Decimal(box l0), Self::Int(r0)) => l0.partial_cmp(&BigDecimal::from(*r0)),
+            (l, r) => match (l.to_ratio(), r.to_ratio()) {
+                (Some((ln, ld)), Some((rn, rd))) => (ln * rd).partial_cmp(&(rn * ld)),
+                _ => l.to_big_decimal().partial_cmp(&r.to_big_decimal())
+            }
         }
     }
 }
 
-impl Pow<Number> for Number {
-    type Output = Number;
-
-    fn pow(self, rhs: Number) -> Self::Output {
-        match (self, rhs) {
-            //TODO: Handle powers of negative numbers elegantly
-            (Number::Int(a), Number::Int(b)) => match a.checked_pow(b as u32) {
-                Some(r) => Number::Int(r),
-                None => Number::Decimal(Box::new(BigDecimal::from(a))), //TODO: Powers dont have method for this lirary, might have to go back to drawing board
+impl Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Number::Int(n) => write!(f, "{}", n),
+            Number::BigInt(box n) => write!(f, "{}", n),
+            Number::Rational(box n, box d) => write!(f, "{}/{}", n, d),
+            Number::Decimal(box d) if Number::too_long_to_write_out(d) => {
+                let (mantissa, scale) = d.as_bigint_and_exponent();
+                write!(f, "{}e{}", mantissa, -scale)
             },
-            (Number::Int(_), Number::Decimal(_)) => todo!(),
-            (Number::Decimal(_), Number::Int(_)) => todo!(),
-            (Number::Decimal(_), Number::Decimal(_)) => panic!("Powers of Big Decimals is not yet implemented"),
+            Number::Decimal(box d) => write!(f, "{}", d.to_string()),
         }
     }
 }
@@ -77,25 +278,15 @@ impl Mul<Number> for Number {
         match (self, rhs) {
             (Number::Int(a), Number::Int(b)) => match a.checked_mul(b) {
                 Some(r) => Number::Int(r),
-                None => Number::Decimal(Box::new(BigDecimal::from(a) * BigDecimal::from(b)))
+                None => Number::from_big_int(BigInt::from(a) * BigInt::from(b))
             },
             (Number::Int(a), Number::Decimal(box b)) => Number::Decimal(Box::new(BigDecimal::from(a) * b)),
-            (Number::Decimal(box a), Number::Int(b)) => Number::Decimal(Box::new(BigDecimal::from(b) * a)),
+            (Number::Decimal(box a), Number::Int(b)) => Number::Decimal(Box::new(a * BigDecimal::from(b))),
             (Number::Decimal(box a), Number::Decimal(box b)) => Number::Decimal(Box::new(a * b)),
-        }
-    }
-}
-
-impl Div<Number> for Number {
-    type Output = Number;
-
-    // May need to revisit this one
-    fn div(self, rhs: Number) -> Self::Output {
-        match (self, rhs) {
-            (Number::Int(a), Number::Int(b)) => if a % b == 0 { Number::Int(a / b) } else { Number::Decimal(Box::new(BigDecimal::from(a) / BigDecimal::from(b))) },
-            (Number::Int(a), Number::Decimal(box b)) => Number::Decimal(Box::new(BigDecimal::from(a) / b)),
-            (Number::Decimal(box a), Number::Int(b)) => Number::Decimal(Box::new(BigDecimal::from(b) / a)),
-            (Number::Decimal(box a), Number::Decimal(box b)) => Number::Decimal(Box::new(a / b)),
+            (a, b) => match a.exact_pair(&b) {
+                Some(((an, ad), (bn, bd))) => Number::over_denominators(an * bn, ad * bd),
+                None => Number::Decimal(Box::new(a.to_big_decimal() * b.to_big_decimal()))
+            }
         }
     }
 }
@@ -107,11 +298,15 @@ impl Add<Number> for Number {
         match (self, rhs) {
             (Number::Int(a), Number::Int(b)) => match a.checked_add(b) {
                 Some(r) => Number::Int(r),
-                None => Number::Decimal(Box::new(BigDecimal::from(a) + BigDecimal::from(b)))
+                None => Number::from_big_int(BigInt::from(a) + BigInt::from(b))
             },
             (Number::Int(a), Number::Decimal(box b)) => Number::Decimal(Box::new(BigDecimal::from(a) + b)),
-            (Number::Decimal(box a), Number::Int(b)) => Number::Decimal(Box::new(BigDecimal::from(b) + a)),
+            (Number::Decimal(box a), Number::Int(b)) => Number::Decimal(Box::new(a + BigDecimal::from(b))),
             (Number::Decimal(box a), Number::Decimal(box b)) => Number::Decimal(Box::new(a + b)),
+            (a, b) => match a.exact_pair(&b) {
+                Some(((an, ad), (bn, bd))) => Number::over_denominators(an * &bd + bn * &ad, ad * bd),
+                None => Number::Decimal(Box::new(a.to_big_decimal() + b.to_big_decimal()))
+            }
         }
     }
 }
@@ -124,14 +319,259 @@ impl Sub<Number> for Number {
         match (self, rhs) {
             (Number::Int(a), Number::Int(b)) => match a.checked_sub(b) {
                 Some(r) => Number::Int(r),
-                None => Number::Decimal(Box::new(BigDecimal::from(a) - BigDecimal::from(b)))
+                None => Number::from_big_int(BigInt::from(a) - BigInt::from(b))
             },
             (Number::Int(a), Number::Decimal(box b)) => Number::Decimal(Box::new(BigDecimal::from(a) - b)),
-            (Number::Decimal(box a), Number::Int(b)) => Number::Decimal(Box::new(BigDecimal::from(b) - a)),
+            (Number::Decimal(box a), Number::Int(b)) => Number::Decimal(Box::new(a - BigDecimal::from(b))),
             (Number::Decimal(box a), Number::Decimal(box b)) => Number::Decimal(Box::new(a - b)),
+            (a, b) => match a.exact_pair(&b) {
+                Some(((an, ad), (bn, bd))) => Number::over_denominators(an * &bd - bn * &ad, ad * bd),
+                None => Number::Decimal(Box::new(a.to_big_decimal() - b.to_big_decimal()))
+            }
         }
     }
 }
 
+impl Neg for Number {
+    type Output = Number;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Number::Int(a) => match a.checked_neg() {
+                Some(r) => Number::Int(r),
+                None => Number::BigInt(Box::new(-BigInt::from(a)))
+            },
+            Number::BigInt(box a) => Number::from_big_int(-a),
+            Number::Rational(n, d) => Number::Rational(Box::new(-*n), d),
+            Number::Decimal(box a) => Number::Decimal(Box::new(-a)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::Signed;
+    use crate::testing::Random;
+
+    fn int(n: i64) -> Number { Number::Int(n) }
+
+    fn div(a: Number, b: Number) -> Number { a.checked_div(&b).unwrap() }
+
+    #[test]
+    fn test_big_integers() {
+        let two_to_the_100 = int(2).pow(&int(100)).unwrap();
+        assert_eq!(two_to_the_100.to_string(), "1267650600228229401496703205376");
+        assert!(matches!(two_to_the_100, Number::BigInt(_)));
+
+        // Results that fit again go back to being an Int
+        assert!(matches!(two_to_the_100.clone() - two_to_the_100.clone() + int(1), Number::Int(1)));
+        assert!(matches!(div(two_to_the_100.clone(), int(2).pow(&int(98)).unwrap()), Number::Int(4)));
+        assert!(matches!(-(-int(i64::MAX) - int(1)), Number::BigInt(_)));
+        assert!(matches!(-(-(-int(i64::MAX) - int(1))), Number::Int(i64::MIN)));
+
+        assert_eq!((int(i64::MAX) * int(i64::MAX)).to_string(), "85070591730234615847396907784232501249");
+        assert_eq!((int(i64::MAX) + int(1)).to_string(), "9223372036854775808");
+        assert_eq!(div(two_to_the_100.clone(), int(3)).to_string(), "1267650600228229401496703205376/3");
+        assert_eq!((two_to_the_100.clone() * Number::parse("0.5").unwrap()).to_string(), "633825300114114700748351602688.0");
+
+        assert!(two_to_the_100 > int(i64::MAX) && -two_to_the_100.clone() < int(i64::MIN));
+        assert!(int(2).pow(&int(64)).unwrap() == Number::parse("18446744073709551616").unwrap());
+        assert!(int(2).pow(&int(64)).unwrap() == Number::parse("18446744073709551616.0").unwrap());
+        assert_eq!(int(2).pow(&int(128)).unwrap().pow(&div(int(1), int(2))).map(|n| n.to_string()), Some("18446744073709551616".to_string()));
+    }
+
+    #[test]
+    fn test_rationals() {
+        let third = div(int(1), int(3));
+        assert_eq!(third.to_string(), "1/3");
+        assert!(matches!(int(3) * third.clone(), Number::Int(1)));
+        assert_eq!((third.clone() + div(int(1), int(6))).to_string(), "1/2");
+        assert_eq!((third.clone() - int(1)).to_string(), "-2/3");
+        assert_eq!(div(int(4), int(-6)).to_string(), "-2/3");
+        assert_eq!(div(third.clone(), div(int(2), int(9))).to_string(), "3/2");
+        assert_eq!((-third.clone()).pow(&int(2)).unwrap().to_string(), "1/9");
+        assert_eq!(div(int(4), int(9)).pow(&div(int(1), int(2))).map(|n| n.to_string()), Some("2/3".to_string()));
+        assert!(matches!(div(int(i64::MIN), int(-1)), Number::BigInt(_)));
+        assert!(int(1).checked_div(&int(0)).is_none() && third.checked_div(&Number::parse("0.0").unwrap()).is_none());
+        assert!(Number::from_ratio(BigInt::one(), BigInt::zero()).is_none());
+
+        // Decimals stay decimals, but compare exactly against fractions
+        let product = third.clone() * Number::parse("1.5").unwrap();
+        assert!(matches!(product, Number::Decimal(_)) && product.to_string().starts_with("0.49999999999999"));
+        assert!(div(int(1), int(2)) == Number::parse("0.5").unwrap());
+        assert!(third < Number::parse("0.3334").unwrap() && third > Number::parse("0.3333").unwrap());
+        assert!(div(int(-1), int(3)) < int(0));
+        assert_eq!((Number::parse("0.5").unwrap() - int(1)).to_string(), "-0.5");
+        assert_eq!(div(Number::parse("1.5").unwrap(), int(3)).to_string(), "0.5");
+
+        assert_eq!(int(12).gcd(&int(-18)).unwrap().to_string(), "6");
+        assert_eq!(div(int(1), int(2)).gcd(&third).unwrap().to_string(), "1/6");
+        assert_eq!(Number::parse("1.5").unwrap().gcd(&int(2)).unwrap().to_string(), "1/2");
+        assert_eq!(int(0).gcd(&int(0)).unwrap().to_string(), "0");
+
+        // Decimals too long to write out are compared without working out their exact value
+        let huge = Number::parse("1e999999999").unwrap();
+        assert!(huge > div(int(1), int(2)) && huge != third && Number::parse("-1e999999999").unwrap() < third);
+        assert!(Number::parse("1e-999999999").unwrap() < third);
+        assert!(huge.gcd(&int(2)).is_none());
+    }
+
+    #[test]
+    fn test_powers() {
+        let pow = |a: &str, b: &str| Number::parse(a).unwrap().pow(&Number::parse(b).unwrap()).map(|n| n.to_string());
+        let fraction = |a: i64, b: i64| div(int(a), int(b));
+
+        assert_eq!(int(2).pow(&int(-3)).map(|n| n.to_string()), Some("1/8".to_string()));
+        assert_eq!(int(-2).pow(&int(-3)).map(|n| n.to_string()), Some("-1/8".to_string()));
+        assert_eq!(fraction(2, 3).pow(&int(-2)).map(|n| n.to_string()), Some("9/4".to_string()));
+        assert_eq!(int(4).pow(&fraction(1, 2)).map(|n| n.to_string()), Some("2".to_string()));
+        assert_eq!(int(8).pow(&fraction(-2, 3)).map(|n| n.to_string()), Some("1/4".to_string()));
+        assert_eq!(int(-8).pow(&fraction(1, 3)).map(|n| n.to_string()), Some("-2".to_string()));
+        assert_eq!(fraction(9, 4).pow(&fraction(3, 2)).map(|n| n.to_string()), Some("27/8".to_string()));
+        assert_eq!(int(0).pow(&int(0)).map(|n| n.to_string()), Some("1".to_string()));
+
+        assert_eq!(pow("1.5", "2"), Some("2.25".to_string()));
+        assert_eq!(pow("2", "-1.0"), Some("0.5".to_string()));
+        assert_eq!(pow("2.25", "0.5"), Some("1.5".to_string()));
+        assert_eq!(pow("0.1", "-2"), Some("100".to_string()));
+
+        // Anything without an exact answer is None
+        assert_eq!(int(2).pow(&fraction(1, 2)), None);
+        assert_eq!(int(-4).pow(&fraction(1, 2)), None);
+        assert_eq!(int(0).pow(&int(-1)), None);
+        assert_eq!(pow("2", "0.3"), None);
+        assert_eq!(int(3).pow(&int(1 << 40)), None);
+        assert_eq!(int(2).pow(&Number::parse("1e999999999").unwrap()), None);
+    }
+
+    // Every operator is checked against plain fraction arithmetic, the samples are built straight from their exact values so the
+    // reference doesn't lean on any of the conversions being tested
+    type Ratio = (BigInt, BigInt);
+
+    fn reduce((n, d): Ratio) -> Ratio {
+        let divisor = gcd(&n, &d);
+        if d < BigInt::zero() { (-n / &divisor, -d / &divisor) } else { (n / &divisor, d / &divisor) }
+    }
+
+    fn exact(n: &Number) -> Ratio {
+        match n {
+            Number::Int(n) => (BigInt::from(*n), BigInt::one()),
+            Number::BigInt(box n) => (n.clone(), BigInt::one()),
+            Number::Rational(box n, box d) => (n.clone(), d.clone()),
+            Number::Decimal(box d) => {
+                let (digits, scale) = d.as_bigint_and_exponent();
+                if scale < 0 { (digits * BigInt::from(10).pow(-scale as u32), BigInt::one()) } else { reduce((digits, BigInt::from(10).pow(scale as u32))) }
+            }
+        }
+    }
+
+    fn small_int(random: &mut Random) -> i64 {
+        random.next(2001) as i64 - 1000
+    }
+
+    fn big_int(random: &mut Random) -> BigInt {
+        let sign = if random.next(2) == 0 { 1 } else { -1 };
+        BigInt::from(sign) * (BigInt::from(random.next_u64()) * BigInt::from(random.next_u64()) + BigInt::from(u64::MAX))
+    }
+
+    // One sample of each variant, along with its exact value
+    fn samples(random: &mut Random) -> Vec<(Number, Ratio)> {
+        let edges = [0, 1, -1, i64::MAX, i64::MIN, i64::MAX / 2 + 1];
+        let int = match random.next(3) {
+            0 => edges[random.next(edges.len())],
+            1 => random.next_u64() as i64,
+            _ => small_int(random)
+        };
+        let big = big_int(random);
+
+        let mut denominator = BigInt::from(random.next(1000) + 2);
+        if random.next(4) == 0 { denominator *= big_int(random); }
+        let (numerator, denominator) = reduce((BigInt::from(small_int(random)) * BigInt::from(random.next(7) + 1) + BigInt::one(), denominator.abs()));
+        let (numerator, denominator) = if denominator.is_one() { (numerator, BigInt::from(3)) } else { (numerator, denominator) };
+        let (numerator, denominator) = reduce((numerator, denominator));
 
+        let digits = if random.next(3) == 0 { big_int(random) } else { BigInt::from(small_int(random)) };
+        let scale = random.next(12) as i64 - 2;
+        let decimal = (Number::Decimal(Box::new(BigDecimal::new(digits.clone(), scale))), exact(&Number::Decimal(Box::new(BigDecimal::new(digits, scale)))));
+
+        vec![
+            (Number::Int(int), (BigInt::from(int), BigInt::one())),
+            (Number::BigInt(Box::new(big.clone())), (big, BigInt::one())),
+            if denominator.is_one() { (Number::from_big_int(numerator.clone()), (numerator, denominator)) } else { (Number::Rational(Box::new(numerator.clone()), Box::new(denominator.clone())), (numerator, denominator)) },
+            decimal
+        ]
+    }
+
+    // Results need to be in their simplest form, and only involving a decimal gives a decimal back
+    fn check(result: &Number, expected: Ratio, inexact: bool, description: &str) {
+        let expected = reduce(expected);
+        match result {
+            Number::Decimal(_) => assert!(inexact, "{} should be exact but gave {}", description, result),
+            _ => assert!(!inexact, "{} should be a decimal but gave {}", description, result)
+        }
+
+        match result {
+            Number::BigInt(box n) => assert!(n.to_i64().is_none(), "{} gave a BigInt that fits in an Int", description),
+            Number::Rational(box n, box d) => assert!(*d > BigInt::one() && gcd(n, d).is_one(), "{} gave {} which isn't in lowest terms", description, result),
+            _ => {}
+        }
+
+        let (n, d) = exact(result);
+        if inexact {
+            // Decimal division rounds, so these only need to be very close
+            let difference = (n * &expected.1 - &expected.0 * &d).abs();
+            let tolerance = (expected.0.abs() * &d).max(&expected.1 * &d) / BigInt::from(10).pow(40u32);
+            assert!(difference <= tolerance, "{} gave {}, expected {}/{}", description, result, expected.0, expected.1);
+        } else {
+            assert!((n.clone(), d.clone()) == expected, "{} gave {}, expected {}/{}", description, result, expected.0, expected.1);
+        }
+    }
+
+    #[test]
+    fn test_operators_against_reference() {
+        let mut random = Random(0x853C49E6748FEA9B);
+        let names = ["Int", "BigInt", "Rational", "Decimal"];
+
+        for _ in 0..150 {
+            let left = samples(&mut random);
+            let right = samples(&mut random);
+
+            for (i, (a, (an, ad))) in left.iter().enumerate() {
+                let (an, ad) = (an.clone(), ad.clone());
+
+                check(&-a.clone(), (-an.clone(), ad.clone()), i == 3, &format!("-{} ({})", a, names[i]));
+                assert!(a.clone().abs() >= Number::Int(0));
+
+                for (j, (b, (bn, bd))) in right.iter().enumerate() {
+                    let description = |op: &str| format!("{} {} {} ({} {} {})", a, op, b, names[i], op, names[j]);
+                    let inexact = i == 3 || j == 3;
+
+                    check(&(a.clone() + b.clone()), (&an * bd + bn * &ad, &ad * bd), inexact, &description("+"));
+                    check(&(a.clone() - b.clone()), (&an * bd - bn * &ad, &ad * bd), inexact, &description("-"));
+                    check(&(a.clone() * b.clone()), (&an * bn, &ad * bd), inexact, &description("*"));
+                    match a.checked_div(b) {
+                        Some(result) => check(&result, (&an * bd, &ad * bn), inexact, &description("/")),
+                        None => assert!(bn.is_zero(), "{} had no result", description("/"))
+                    }
+
+                    let ordering = (&an * bd).cmp(&(bn * &ad));
+                    assert_eq!(a.partial_cmp(b), Some(ordering), "{}", description("<=>"));
+                    assert_eq!(a == b, ordering == Ordering::Equal, "{}", description("=="));
+                }
+
+                for exponent in -3i64..=3 {
+                    let description = format!("{}^{} ({})", a, exponent, names[i]);
+                    let power = exponent.unsigned_abs() as u32;
+                    match a.pow(&Number::Int(exponent)) {
+                        None => assert!(an.is_zero() && exponent < 0, "{} had no result", description),
+                        Some(result) if exponent < 0 => check(&result, (ad.clone().pow(power), an.clone().pow(power)), i == 3, &description),
+                        Some(result) => check(&result, (an.clone().pow(power), ad.clone().pow(power)), i == 3, &description)
+                    }
+                }
+            }
+        }
+    }
 
+}
\ No newline at end of file
@@ -0,0 +1,121 @@
+
+pub const DEFAULT_PRECEDENCE: usize = 9;
+
+/// Which side an operation groups towards when chained with operations of the same precedence ie: "a - b - c" is "(a - b) - c" but "a^b^c" is "a^(b^c)"
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Associativity {
+    Left,
+    Right
+}
+
+/// Prefix operations take a single operand to their right (Node::LOp), Infix operations sit between two operands (Node::Op)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arity {
+    Prefix,
+    Infix
+}
+
+#[derive(Clone, Debug)]
+pub struct OperatorDef {
+    symbol: String,
+    precedence: usize,
+    associativity: Associativity,
+    arity: Arity
+}
+
+/// The single source of truth for how operators bind, both the shunting yard algorithm and the printer read from this table so they can't disagree.
+/// A higher precedence binds tighter. Scripts can extend their table with declarations like `infix 7 left .`
+/// Infix operators that have never been declared behave like `infix 9 left`, prefix operators must always be declared.
+#[derive(Clone, Debug)]
+pub struct OperatorTable {
+    operators: Vec<OperatorDef>
+}
+
+
+impl OperatorDef {
+
+    pub fn new(symbol: &str, precedence: usize, associativity: Associativity, arity: Arity) -> Self {
+        OperatorDef { symbol: symbol.to_string(), precedence, associativity, arity }
+    }
+
+    pub fn get_symbol(&self) -> &String { &self.symbol }
+
+    pub fn get_precedence(&self) -> usize { self.precedence }
+
+    pub fn get_associativity(&self) -> Associativity { self.associativity }
+
+    pub fn get_arity(&self) -> Arity { self.arity }
+
+}
+
+
+impl OperatorTable {
+
+    pub fn new() -> Self {
+        OperatorTable { operators: Vec::new() }
+    }
+
+    /// Adding an operator that already exists with the same arity replaces the previous definition
+    pub fn add(&mut self, operator: OperatorDef) {
+        self.operators.retain(|o| !(o.symbol == operator.symbol && o.arity == operator.arity));
+        self.operators.push(operator);
+    }
+
+    pub fn get(&self, symbol: &str, arity: Arity) -> Option<&OperatorDef> {
+        self.operators.iter().find(|o| o.symbol == symbol && o.arity == arity)
+    }
+
+    pub fn infix(&self, symbol: &str) -> Option<&OperatorDef> {
+        self.get(symbol, Arity::Infix)
+    }
+
+    pub fn infix_or_default(&self, symbol: &str) -> OperatorDef {
+        self.infix(symbol).cloned().unwrap_or(OperatorDef::new(symbol, DEFAULT_PRECEDENCE, Associativity::Left, Arity::Infix))
+    }
+
+    pub fn prefix(&self, symbol: &str) -> Option<&OperatorDef> {
+        self.get(symbol, Arity::Prefix)
+    }
+
+    pub fn get_operators(&self) -> &Vec<OperatorDef> {
+        &self.operators
+    }
+
+    /// Operators from other take priority over ones already in this table
+    pub fn merge(&mut self, other: &Self) {
+        for operator in other.get_operators() {
+            self.add(operator.clone());
+        }
+    }
+
+}
+
+impl Default for OperatorTable {
+
+    // Prefix operations bind tighter than "*" and "/" but looser than "^", so "-x^2" is "-(x^2)"
+    fn default() -> Self {
+        use Associativity::*;
+        use Arity::*;
+
+        let mut table = OperatorTable::new();
+
+        table.add(OperatorDef::new("=", 0, Left, Infix));
+        table.add(OperatorDef::new("$", 1, Left, Infix));
+        table.add(OperatorDef::new("==", 4, Left, Infix));
+        table.add(OperatorDef::new("!=", 4, Left, Infix));
+        table.add(OperatorDef::new("<", 4, Left, Infix));
+        table.add(OperatorDef::new(">", 4, Left, Infix));
+        table.add(OperatorDef::new("<=", 4, Left, Infix));
+        table.add(OperatorDef::new(">=", 4, Left, Infix));
+        table.add(OperatorDef::new("+", 6, Left, Infix));
+        table.add(OperatorDef::new("-", 6, Left, Infix));
+        table.add(OperatorDef::new("*", 7, Left, Infix));
+        table.add(OperatorDef::new("/", 7, Left, Infix));
+        table.add(OperatorDef::new("%", 7, Left, Infix));
+        table.add(OperatorDef::new("-", 8, Right, Prefix));
+        table.add(OperatorDef::new("!", 8, Right, Prefix));
+        table.add(OperatorDef::new("^", 9, Right, Infix));
+
+        table
+    }
+}
@@ -17,6 +17,14 @@ impl SymbolTable {
         self.table.insert(key, value);
     }
 
+    pub fn remove(&mut self, key: &String) -> Option<Expression> {
+        self.table.remove(key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Expression> {
+        self.table.values()
+    }
+
     pub fn get_args(&self, args: &[String]) -> Option<Box<[Expression]>> {
         let mut result = Vec::new();
         for arg in args.iter() {
@@ -0,0 +1,237 @@
+// Finds, parses and caches the modules that scripts import with "use", ie: "use calculus" looks for a file called calculus.hydra
+
+use std::{collections::{HashMap, HashSet}, env, fs, path::{Path, PathBuf}};
+
+use crate::{builtin::base::stdlib_modules, model::{Script, error::{DSLError, SourceSpan}, operator::OperatorTable}};
+use super::parser::parse_script_with_loader;
+
+const MODULE_EXTENSION: &str = "hydra";
+
+/// Modules are looked for in the directory of the script doing the importing, then each directory of the search path in order,
+/// and finally in the scripts embedded in the binary, so a file on disk can stand in for part of the standard library.
+/// Each module is parsed at most once per loader, and its definitions are only handed out the first time it is imported
+/// so a module that is reachable from two different imports doesn't end up with its functions defined twice.
+#[derive(Clone)]
+pub struct ModuleLoader {
+    search_path: Vec<PathBuf>,
+    embedded: HashMap<String, &'static str>,
+    modules: HashMap<String, Script>,
+    merged: HashSet<String>,
+    // Modules part way through being loaded along with the directory they live in, used to resolve nested imports and detect cycles
+    loading: Vec<(String, Option<PathBuf>)>
+}
+
+impl ModuleLoader {
+
+    /// A loader that only knows about the given directories, nothing is embedded
+    pub fn new(search_path: Vec<PathBuf>) -> Self {
+        ModuleLoader { search_path, embedded: HashMap::new(), modules: HashMap::new(), merged: HashSet::new(), loading: Vec::new() }
+    }
+
+    /// Searches directory before anything else, this is where the script doing the importing lives
+    pub fn with_directory(mut self, directory: &Path) -> Self {
+        self.search_path.insert(0, directory.to_path_buf());
+        self
+    }
+
+    pub fn add_embedded(&mut self, name: &str, source: &'static str) {
+        self.embedded.insert(name.to_string(), source);
+    }
+
+    pub fn get_search_path(&self) -> &Vec<PathBuf> {
+        &self.search_path
+    }
+
+    /// Finds and parses the module called name, returning the key it is stored against. span is where the import happened and is used for errors
+    pub fn resolve(&mut self, name: &str, span: SourceSpan) -> Result<String, DSLError> {
+        let import_error = |message: String, errors: Vec<DSLError>| DSLError::ImportError { message, span: span.clone(), errors };
+
+        let file_name = format!("{}.{}", name, MODULE_EXTENSION);
+        let current_directory = self.loading.last().and_then(|(_, directory)| directory.clone());
+        let found = current_directory.iter().chain(self.search_path.iter()).map(|d| d.join(&file_name)).find(|p| p.is_file());
+
+        let (key, directory) = match &found {
+            Some(path) => {
+                let path = path.canonicalize().unwrap_or(path.clone());
+                (path.display().to_string(), path.parent().map(|p| p.to_path_buf()))
+            },
+            None if self.embedded.contains_key(name) => (format!("<embedded>/{}", file_name), None),
+            None => return Err(import_error(format!("Unable to find module \"{}\"", name), Vec::new()))
+        };
+
+        if let Some(position) = self.loading.iter().position(|(k, _)| k == &key) {
+            let cycle = self.loading[position..].iter().map(|(k, _)| module_name(k)).chain(std::iter::once(name.to_string())).collect::<Vec<String>>();
+            return Err(import_error(format!("Circular import {}", cycle.join(" -> ")), Vec::new()));
+        }
+
+        if self.modules.contains_key(&key) {
+            return Ok(key);
+        }
+
+        let source = match &found {
+            Some(path) => fs::read_to_string(path).map_err(|e| import_error(format!("Unable to read module \"{}\": {}", name, e), Vec::new()))?,
+            None => self.embedded.get(name).unwrap().to_string()
+        };
+
+        self.loading.push((key.clone(), directory));
+        let (parsed, errors) = parse_script_with_loader(&source, &OperatorTable::default(), self);
+        self.loading.pop();
+
+        if !errors.is_empty() {
+            return Err(import_error(format!("Unable to parse module \"{}\"", name), errors));
+        }
+
+        // Only definitions are imported, any expressions in the module are left behind
+        let mut module = Script::new(parsed.get_function_defs(), Vec::new());
+        module.set_operators(parsed.get_operators().clone());
+        module.hide_all_function_defs();
+
+        self.modules.insert(key.clone(), module);
+        Ok(key)
+    }
+
+    /// Operators declared by the module, including any it imported itself
+    pub fn get_operators(&self, key: &str) -> Option<&OperatorTable> {
+        self.modules.get(key).map(|m| m.get_operators())
+    }
+
+    /// The definitions of a resolved module, None if they have already been handed out by this loader
+    pub fn take_definitions(&mut self, key: &str) -> Option<Script> {
+        if self.merged.insert(key.to_string()) {
+            self.modules.get(key).cloned()
+        } else {
+            None
+        }
+    }
+
+}
+
+impl Default for ModuleLoader {
+
+    // Directories in HYDRA_PATH are separated the same way as PATH on the current platform
+    fn default() -> Self {
+        let search_path = env::var_os("HYDRA_PATH").map(|paths| env::split_paths(&paths).collect()).unwrap_or_default();
+        let mut loader = ModuleLoader::new(search_path);
+
+        for (name, source) in stdlib_modules() {
+            loader.add_embedded(name, source);
+        }
+
+        loader
+    }
+}
+
+fn module_name(key: &str) -> String {
+    Path::new(key).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or(key.to_string())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{builtin::base::base_config, model::error::DSLError};
+
+    /// Directory of modules that's removed again once the test is done with it
+    struct ModuleDir(PathBuf);
+
+    impl std::ops::Deref for ModuleDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for ModuleDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // Every test gets its own directory so they can run in parallel
+    fn module_dir(test: &str, modules: &[(&str, &str)]) -> ModuleDir {
+        let dir = ModuleDir(env::temp_dir().join(format!("hydralang_loader_{}_{}", test, std::process::id())));
+        fs::create_dir_all(&*dir).unwrap();
+        for (name, source) in modules {
+            fs::write(dir.join(format!("{}.hydra", name)), source).unwrap();
+        }
+        dir
+    }
+
+    fn run(input: &str, loader: &mut ModuleLoader) -> Result<String, Vec<DSLError>> {
+        let mut script = base_config();
+        let (parsed, errors) = parse_script_with_loader(input, script.get_operators(), loader);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        script.merge(&parsed);
+        script.run().map_err(|e| vec![e])?;
+        Ok(script.to_string().trim().to_string())
+    }
+
+    #[test]
+    fn test_import_from_directory() {
+        let dir = module_dir("directory", &[
+            ("shapes", "use squares\nrectangle(w, h) = w * h\n"),
+            ("squares", "infix 5 left ..\nsquare(x) = x ^ 2\neval(a .. b) = a * b + 1 where { isNum(a), isNum(b) }\n")
+        ]);
+        let mut loader = ModuleLoader::new(Vec::new()).with_directory(&dir);
+
+        // Operators declared in a module are understood by the lines after the import
+        assert_eq!(run("use shapes\nrectangle(2, 3) + square(3)\n2 + 3 .. 4", &mut loader).unwrap(), "15\n21");
+    }
+
+    #[test]
+    fn test_import_once() {
+        let dir = module_dir("once", &[
+            ("left", "use shared\nl(x) = s(x)\n"),
+            ("right", "use shared\nr(x) = s(x)\n"),
+            ("shared", "s(x) = x + 1\n")
+        ]);
+        let mut loader = ModuleLoader::new(Vec::new()).with_directory(&dir);
+        let (script, errors) = parse_script_with_loader("use left\nuse right\nuse shared", &OperatorTable::default(), &mut loader);
+
+        assert!(errors.is_empty());
+        assert_eq!(script.get_function_defs().len(), 3);
+
+        // Going back to a copy taken before an import means it can be imported again, ie: when the line doing it is thrown away
+        let mut loader = ModuleLoader::new(Vec::new()).with_directory(&dir);
+        let before = loader.clone();
+        parse_script_with_loader("use shared", &OperatorTable::default(), &mut loader);
+        assert_eq!(parse_script_with_loader("use shared", &OperatorTable::default(), &mut loader).0.get_function_defs().len(), 0);
+        let mut loader = before;
+        assert_eq!(parse_script_with_loader("use shared", &OperatorTable::default(), &mut loader).0.get_function_defs().len(), 1);
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let dir = module_dir("errors", &[
+            ("a", "use b\n"),
+            ("b", "use a\n"),
+            ("broken", "f(x) = x +\n")
+        ]);
+        let mut loader = ModuleLoader::new(Vec::new()).with_directory(&dir);
+
+        let message = |input: &str, loader: &mut ModuleLoader| run(input, loader).unwrap_err().remove(0).to_string();
+
+        assert!(message("use missing", &mut loader).contains("Unable to find module \"missing\""));
+        assert!(message("use a", &mut loader).contains("Circular import a -> b -> a"));
+
+        let broken = message("1 + 1\nuse broken", &mut loader);
+        assert!(broken.contains("Unable to parse module \"broken\" at line 2, column 1"));
+        assert!(broken.contains("does not have anything on its right"));
+    }
+
+    #[test]
+    fn test_embedded_modules() {
+        let dir = module_dir("embedded", &[]);
+        let mut loader = ModuleLoader::default().with_directory(&dir);
+
+        assert_eq!(run("use base\ndf(3 * x ^ 2, x)", &mut loader).unwrap(), "6 * x");
+        // Imported definitions are hidden so they aren't printed as part of the script
+        assert!(!run("use base\n1", &mut ModuleLoader::default()).unwrap().contains("df"));
+        // "use" needs to be a word of its own
+        assert_eq!(run("user + 1", &mut ModuleLoader::new(Vec::new())).unwrap(), "user + 1");
+    }
+
+}
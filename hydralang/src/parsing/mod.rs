@@ -1,2 +1,3 @@
+pub mod loader;
 pub mod parser;
 pub mod tokenizer;
\ No newline at end of file
@@ -2,12 +2,14 @@
 
 // Parser is actually quite simple after tokenizer and shunting yard algorithm are applied, simply exists to map tokens to enums
 
-use crate::{model::{ Expression, Script, expression::Node, function::FunctionDef, error::DSLError, number::Number }, parsing::tokenizer::{tokenize_statement, tokenize_script}, stack::Stack};
-use super::tokenizer::{OperandType, TokenStream};
+use crate::{model::{ Expression, Script, Statement, expression::Node, function::FunctionDef, error::DSLError, number::Number, operator::OperatorTable }, parsing::tokenizer::{tokenize_statement, tokenize_script_recovering}, stack::Stack};
+use super::{tokenizer::{OperandType, TokenStream, TokenFunctionDef, TokenStatement}, loader::ModuleLoader};
 
 fn parse_tokens(tokens: TokenStream) -> Result<Expression, DSLError> {
 
-    let mut operands = Stack::<Node>::new();
+    // Alongside each operand we keep whether it was written as a number literal, "-3" is the number -3 where "-(3)" negates the number 3.
+    // The same goes for fractions, "1/3" is the number 1/3 where "(1) / 3" divides 1 by 3
+    let mut operands = Stack::<(Node, bool)>::new();
 
     for token in tokens {
         match token {
@@ -15,15 +17,32 @@ fn parse_tokens(tokens: TokenStream) -> Result<Expression, DSLError> {
 
                 let right = operands.pop().ok_or(DSLError::ParserError(format!("Invalid syntax {} does not have anything to operate on.", op), None))?;
                 let left = operands.pop().ok_or(DSLError::ParserError(format!("Invalid syntax {} does not have anything to operate on.", op), None))?;
-                let newnode = Node::Op(op, Box::new(left), Box::new(right));
-                operands.push(newnode);
+                let newnode = match (left, right) {
+                    ((Node::Num(n), true), (Node::Num(d), true)) if op == "/" && Node::is_fraction_of(&n, &d) => Node::Num(n.checked_div(&d).expect("Denominator is positive")),
+                    ((left, _), (right, _)) if op == "=" => Node::Equation(Box::new(left), Box::new(right)),
+                    ((left, _), (right, _)) => Node::Op(op, Box::new(left), Box::new(right))
+                };
+                operands.push((newnode, false));
+
+            },
+            super::tokenizer::Token::PrefixOperation(op) => {
+
+                let child = operands.pop().ok_or(DSLError::ParserError(format!("Invalid syntax {} does not have anything to operate on.", op), None))?;
+                operands.push(match child {
+                    // Still a literal so "-1/3" is a fraction too
+                    (Node::Num(n), true) if op == "-" => (Node::Num(-n), true),
+                    (child, _) => (Node::LOp(op, Box::new(child)), false)
+                });
 
             },
             super::tokenizer::Token::Operand(op) => {
-                operands.push(
+                let is_literal = matches!(op, OperandType::Number(_));
+                operands.push((
                     match op {
-                        OperandType::Number(s) => Node::Num(Number::parse(s.as_str())),
+                        OperandType::Number(s) => Node::Num(Number::parse(s.as_str())?),
                         OperandType::Var(s) => Node::Var(s),
+                        OperandType::Str(s) => Node::Str(s),
+                        OperandType::Group(tokens) => parse_tokens(tokens)?.get_root_node().clone(),
                         OperandType::Vector(v) => {
                             let mut parsed_vec = Vec::new();
 
@@ -33,6 +52,16 @@ fn parse_tokens(tokens: TokenStream) -> Result<Expression, DSLError> {
 
                             Node::Vector(parsed_vec)
                         },
+                        OperandType::Lambda { params, body } => Node::Lambda { params, body: Box::new(parse_tokens(body)?.get_root_node().clone()) },
+                        OperandType::Matrix { rows, cols, data } => {
+                            let mut parsed_data = Vec::new();
+
+                            for token_stream in data {
+                                parsed_data.push(parse_tokens(token_stream)?.get_root_node().clone())
+                            }
+
+                            Node::Matrix { rows, cols, data: parsed_data }
+                        },
                         OperandType::FunctionCall { name, args } => {
                             let mut parsed_args = Vec::new();
 
@@ -42,12 +71,12 @@ fn parse_tokens(tokens: TokenStream) -> Result<Expression, DSLError> {
 
                             Node::FunctionCall { name,  args: parsed_args }
                         },
-                });
+                }, is_literal));
             },
         }
     }
 
-    operands.pop().map(|n| Expression::new(n) ).ok_or(DSLError::RuntimeException)
+    operands.pop().map(|(n, _)| Expression::new(n) ).ok_or(DSLError::ParserError("Expected an expression".to_string(), None))
 
 }
 
@@ -60,51 +89,106 @@ pub fn parse_statement(input: &str) -> Result<Expression, DSLError> {
 }
 
 pub fn parse_script(input: &str) -> Result<Script, DSLError> {
+    parse_script_with_operators(input, &OperatorTable::default())
+}
+
+// Operators declared in the script are added on top of the provided ones, this allows a script to be parsed in the context of another
+pub fn parse_script_with_operators(input: &str, operators: &OperatorTable) -> Result<Script, DSLError> {
+    let (script, mut errors) = parse_script_recovering(input, operators);
 
+    if errors.is_empty() { Ok(script) } else { Err(errors.remove(0)) }
+}
 
-    let token_script = tokenize_script(input)?;
+/// Parses as much of the script as possible, anything that fails to parse is left out of the returned script and reported in the errors instead
+pub fn parse_script_recovering(input: &str, operators: &OperatorTable) -> (Script, Vec<DSLError>) {
+    parse_script_with_loader(input, operators, &mut ModuleLoader::default())
+}
 
-    let mut script = Script::new(Vec::new(), Vec::new() );
+/// Same as parse_script_recovering, imports are found using loader. Definitions of imported modules come before the script's own
+pub fn parse_script_with_loader(input: &str, operators: &OperatorTable, loader: &mut ModuleLoader) -> (Script, Vec<DSLError>) {
 
-    for function in token_script.function_defs {
+    let (token_script, mut errors) = tokenize_script_recovering(input, operators, loader);
 
-        let mut parsed_function_args = Vec::new();
+    let mut script = Script::new(Vec::new(), Vec::new() );
+    script.set_operators(token_script.operators);
 
-        for arg in function.args {
-            parsed_function_args.push(parse_tokens(arg)?);
+    for key in token_script.imports {
+        if let Some(module) = loader.take_definitions(&key) {
+            for function in module.get_function_defs() {
+                script.add_function_def(function);
+            }
         }
+    }
 
-        let parsed_constraints_iter = function.constraints.iter().map(|s| parse_tokens(s.clone()));
-        let mut parsed_constraints = Vec::new();
-        for constraint in parsed_constraints_iter {
-            parsed_constraints.push(constraint?);
+    for function in token_script.function_defs {
+        match parse_function_def(function) {
+            Ok(parsed_function) => script.add_function_def(parsed_function),
+            Err(e) => errors.push(e)
         }
+    }
 
-        let parsed_function = FunctionDef::new(
-            function.name, 
-            parsed_function_args, 
-            parse_tokens(function.tokens)?, 
-            parsed_constraints,
-            function.annotations.clone()
-        );
-
-        script.add_function_def(parsed_function);
+    for statement in token_script.statements {
+        let parsed_statement = match statement {
+            TokenStatement::Evaluate(tokens) => parse_tokens(tokens).map(Statement::Evaluate),
+            TokenStatement::Assign(name, tokens) => parse_tokens(tokens).map(|e| Statement::Assign(name, e)),
+            TokenStatement::Unset(name) => Ok(Statement::Unset(name)),
+            TokenStatement::Continue { expression, written } => parse_continue(expression, written)
+        };
+
+        match parsed_statement {
+            Ok(parsed_statement) => script.add_statement(parsed_statement),
+            Err(e) => errors.push(e)
+        }
     }
 
-    for expression in token_script.expressions {
-        let parsed_expression = parse_tokens(expression)?;
-        script.add_expression_evaluation(parsed_expression);
+    (script, errors)
+
+}
+
+fn parse_continue(expression: TokenStream, written: Option<TokenStream>) -> Result<Statement, DSLError> {
+    let written = match written {
+        Some(tokens) => Some(parse_tokens(tokens)?),
+        None => None
+    };
+
+    Ok(Statement::Continue { expression: parse_tokens(expression)?, written })
+}
+
+fn parse_function_def(function: TokenFunctionDef) -> Result<FunctionDef, DSLError> {
+
+    let mut parsed_function_args = Vec::new();
+
+    for arg in function.args {
+        parsed_function_args.push(parse_tokens(arg)?);
     }
 
-    Ok(script)
+    let parsed_constraints_iter = function.constraints.iter().map(|s| parse_tokens(s.clone()));
+    let mut parsed_constraints = Vec::new();
+    for constraint in parsed_constraints_iter {
+        parsed_constraints.push(constraint?);
+    }
 
+    Ok(FunctionDef::new(
+        function.name, 
+        parsed_function_args, 
+        parse_tokens(function.tokens)?, 
+        parsed_constraints,
+        function.annotations.clone()
+    ))
 }
 
 
 #[cfg(test)]
 mod tests{
     use super::*;
+    use crate::{model::expression_builder::*, traits::DeepEq, builtin::base::base_config};
 
+    fn run(input: &str) -> Script {
+        let mut script = base_config();
+        script.merge(&parse_script(input).unwrap());
+        script.run().unwrap();
+        script
+    }
 
     #[test]
     fn test_function_parsing() {
@@ -142,6 +226,283 @@ mod tests{
         let script = parse_script(test).unwrap();
     }
 
+    #[test]
+    fn test_error_recovery() {
+        let test = "f(x) = x^2
+        g(x) = )
+        @lazy
+        h(x) = x +
+        f(3)
+        k(x) = 1 )
+        f(x) = 2 * x where { isNum(x) }
+        (1, 2";
+
+        let (script, errors) = parse_script_recovering(test, &OperatorTable::default());
+
+        let lines = errors.iter().map(|e| match e {
+            DSLError::SyntaxError { span, .. } => span.line,
+            _ => 0
+        }).collect::<Vec<usize>>();
+        assert_eq!(lines, vec![2, 4, 6, 8]);
+
+        assert_eq!(script.get_function_defs().len(), 2);
+        assert!(script.get_function_defs().iter().all(|f| f.get_name() == "f" && f.get_annotations().is_empty()));
+        assert!(script.get_expression(0).unwrap().get_root_node().deep_eq(&func_call("f".to_string(), vec![num(3)])));
+        assert!(script.get_expression(1).is_none());
+
+        assert!(parse_script(test).is_err());
+
+        // Columns count from the start of the line, even for an item that starts after an annotation
+        let (_, errors) = parse_script_recovering("f(x) = 1\n@lazy g(x) = )\ninfix 99999999999999999999 left %%", &OperatorTable::default());
+        let spans = errors.iter().map(|e| match e {
+            DSLError::SyntaxError { span, .. } => (span.line, span.column, span.text.as_str(), span.source_line.as_str()),
+            _ => panic!("Expected a syntax error, got {}", e)
+        }).collect::<Vec<_>>();
+        assert_eq!(spans, vec![
+            (2, 14, ")", "@lazy g(x) = )"),
+            (3, 7, "99999999999999999999", "infix 99999999999999999999 left %%")
+        ]);
+    }
+
+    #[test]
+    fn test_infix_declaration() {
+        let test = "infix 7 left .
+        infix 6 right <>
+        dot((x1, y1, z1), (x2, y2, z2)) = x1 * x2 + y1 * y2 + z1 * z2
+        eval((x1, y1, z1) . (x2, y2, z2)) = dot((x1, y1, z1), (x2, y2, z2))
+        u . v / w
+        a <> b <> c
+        a . -b";
+
+        let script = parse_script(test).unwrap();
+
+        let expected = div(op(".", var("u".to_string()), var("v".to_string())), var("w".to_string()));
+        assert!(script.get_expression(0).unwrap().get_root_node().deep_eq(&expected));
+
+        let expected = op("<>", var("a".to_string()), op("<>", var("b".to_string()), var("c".to_string())));
+        assert!(script.get_expression(1).unwrap().get_root_node().deep_eq(&expected));
+
+        let expected = op(".", var("a".to_string()), neg(var("b".to_string())));
+        assert!(script.get_expression(2).unwrap().get_root_node().deep_eq(&expected));
+
+        // Declarations carry over into anything parsed as a continuation of the script
+        let mut base = base_config();
+        base.merge(&script);
+        let mut next = base.parse_continuation("(1, 2, 3) . (4, 5, 6)").unwrap();
+        next.merge(&base);
+        next.run().unwrap();
+        assert!(next.get_expression(0).unwrap().get_root_node().deep_eq(&num(32)));
+    }
+
+    #[test]
+    fn test_negated_definitions() {
+        // Only operators starting with "=" stop a definition, a right side starting with any other operator is still a definition
+        let test = "f(x) = -x
+        myabs(x) = -x where { x < 0 }
+        f(3)";
+
+        let script = parse_script(test).unwrap();
+        assert_eq!(script.get_function_defs().len(), 2);
+        assert!(script.get_expression(0).unwrap().get_root_node().deep_eq(&func_call("f".to_string(), vec![num(3)])));
+
+        let script = run(test);
+        assert!(script.get_expression(0).unwrap().get_root_node().deep_eq(&num(-3)));
+
+        // Printed definitions read back as definitions
+        let printed = parse_script("g(x) = (-x)").unwrap().to_string();
+        let reparsed = parse_script(&printed).unwrap();
+        assert_eq!(reparsed.get_function_defs().len(), 1, "{}", printed);
+        assert!(reparsed.get_expression(0).is_none());
+    }
+
+    #[test]
+    fn test_implicit_multiplication() {
+        let test = "u(t) = (0, -1, -1) + t(4, 2, 0)
+        f(x) = x^2 + 2x + 5
+        g(x) = 3(x + 1)
+        h(n) = n h(n - 1) where { n > 0 }
+        h(0) = 1
+        twice(f, x) = f(f(x))
+        u(1)
+        f(3)
+        g(2)
+        h(4)
+        twice(1, 2)";
+
+        let script = run(test);
+
+        // There's no vector arithmetic in the base config so this is as far as it gets
+        assert!(script.get_expression(0).unwrap().get_root_node().deep_eq(&add(vec3(0, -1, -1), vec3(4, 2, 0))));
+        assert!(script.get_expression(1).unwrap().get_root_node().deep_eq(&num(20)));
+        assert!(script.get_expression(2).unwrap().get_root_node().deep_eq(&num(9)));
+        assert!(script.get_expression(3).unwrap().get_root_node().deep_eq(&num(24)));
+        // f is a known function so f(x) stays a call to it even though f is also an argument, f(f(2)) = f(13)
+        assert!(script.get_expression(4).unwrap().get_root_node().deep_eq(&num(200)));
+    }
+
+    #[test]
+    fn test_leibniz_derivatives() {
+        let test = "use base
+        f(x) = 3x^2
+        d/dx(f(x))
+        d(x^3)/dx";
+
+        let script = run(test);
+
+        assert!(script.get_expression(0).unwrap().get_root_node().deep_eq(&mul(num(6), var("x".to_string()))));
+        assert!(script.get_expression(1).unwrap().get_root_node().deep_eq(&mul(num(3), pow(var("x".to_string()), num(2)))));
+    }
+
+    #[test]
+    fn test_string_patterns() {
+        // A finite automaton over "0" and "1" with states labelled by strings
+        let test = "T(\"A\", \"1\") = \"B\"
+        T(\"A\", \"0\") = \"C\"
+        T(\"B\", t) = \"B\" where { t == \"1\" }
+        T(\"B\", t) = \"C\" where { t == \"0\" }
+        T(\"C\", t) = \"D\" where { isStr(t) }
+        T(\"D\", t) = \"D\"
+        run(input) = fold(chars(input), \"A\", T)
+        run(\"110\")
+        run(\"01\")
+        run(\"1\")
+        T(\"E\", \"1\")";
+
+        let script = run(test);
+
+        let expected = ["\"C\"", "\"D\"", "\"B\"", "T(\"E\", \"1\")"];
+        for (i, e) in expected.iter().enumerate() {
+            assert_eq!(script.get_expression(i).unwrap().to_string(), *e);
+        }
+    }
+
+    #[test]
+    fn test_identifier_definitions() {
+        let test = "vec3(x, y, z) = (x, y, z)
+        my_func(θ) = 2θ
+        ω_0 = 3
+        vec3(1, 2, ω_0)
+        my_func(ω_0)";
+
+        let script = run(test);
+
+        assert_eq!(script.get_expression(0).unwrap().to_string(), "(1, 2, 3)");
+        assert_eq!(script.get_expression(1).unwrap().to_string(), "6");
+    }
+
+    #[test]
+    fn test_assignments() {
+        let test = "x + 1
+        x = 2
+        y = x^2
+        area(r) = k r^2
+        k = 3
+        x == 2
+        y + x
+        area(2)
+        (x) => x + y
+        x = 5
+        y
+        unset(x)
+        x + 1
+        z = z + 1
+        z
+        sq = (x) => x^2
+        sq(3)
+        map((1, 2), sq)
+        t = 2
+        t(4, 2, 0)
+        square(x) = x^2
+        s = square
+        s(t)
+        sq(1, 2)";
+
+        let mut script = run(test);
+
+        // Expressions only see the assignments above them, and y keeps the value x had when it was assigned
+        let expected = ["x + 1", "1", "6", "12", "(x) => x + 4", "4", "x + 1", "z", "9", "(1, 4)", "2 * (4, 2, 0)", "4", "sq(1, 2)"];
+        for (i, e) in expected.iter().enumerate() {
+            assert_eq!(script.get_expression(i).unwrap().to_string(), *e);
+        }
+        assert!(script.to_string().contains("y = 4\n"));
+        assert!(script.to_string().contains("unset(x)\n"));
+
+        // Running again gives the same results
+        script.run().unwrap();
+        assert_eq!(script.get_expression(0).unwrap().to_string(), "x + 1");
+        assert_eq!(script.get_expression(5).unwrap().to_string(), "4");
+
+        // A value starting with an operator is still an assignment rather than an equation
+        let script = run("x = -1\nx + 1");
+        assert_eq!(script.get_expression(0).unwrap().to_string(), "0");
+    }
+
+    #[test]
+    fn test_history() {
+        let test = "ans + 1
+        2 + 3
+        ans * 2
+        y = ans + 10
+        ans(1) + ans(2)
+        f(x) = x^2
+        f(ans)
+        ans(9)
+        map((1, 2), (ans) => ans + 1)";
+
+        let mut script = run(test);
+
+        let expected = ["ans + 1", "5", "10", "ans + 1 + 5", "(ans + 1 + 5)^2", "ans(9)", "(2, 3)"];
+        for (i, e) in expected.iter().enumerate() {
+            assert_eq!(script.get_expression(i).unwrap().to_string(), *e);
+        }
+        assert!(script.to_string().contains("y = 20\n"));
+        assert_eq!(script.get_history().len(), 7);
+
+        // Every run starts from the lines as they were written, so a result that still mentions ans doesn't change when run again
+        let printed = script.to_string();
+        script.run().unwrap();
+        assert_eq!(script.to_string(), printed);
+    }
+
+    #[test]
+    fn test_equations() {
+        let test = "2 = x * 25
+        / 3
+        * 3
+        2x + 4 = 10
+        - 4
+        5
+        -3
+        * 2
+        solve(x + a = b, x) = x = b - a
+        solve(x + 4 = 10, x)";
+
+        let script = run(test);
+
+        let x = || var("x".to_string());
+        assert!(script.get_expression(0).unwrap().get_root_node().deep_eq(&equation(num(2), mul(x(), num(25)))));
+        assert!(script.get_expression(2).unwrap().get_root_node().deep_eq(&equation(num(2), mul(div(mul(x(), num(25)), num(3)), num(3)))));
+
+        // "-3" is only a subtraction when there's an equation to subtract it from
+        let expected = ["2 = x * 25", "2/3 = x * 25 / 3", "2 = x * 25 / 3 * 3", "2 * x + 4 = 10", "2 * x + 4 - 4 = 6", "5", "-3", "-6", "x = 6"];
+        for (i, e) in expected.iter().enumerate() {
+            assert_eq!(script.get_expression(i).unwrap().to_string(), *e);
+        }
+
+        // The rest of the line keeps its usual precedence rather than being one operand
+        let mut script = base_config();
+        script.merge(&parse_script("5\n* 2 + 1\n2 = x\n- x + 1\n^2 * 3").unwrap());
+        assert_eq!(script.to_string().lines().skip_while(|l| *l != "5").collect::<Vec<&str>>(), ["5", "* 2 + 1", "2 = x", "- x + 1", "^2 * 3"]);
+        script.run().unwrap();
+        assert_eq!(script.get_expression(1).unwrap().to_string(), "11");
+        assert!(script.get_expression(3).unwrap().get_root_node().deep_eq(&equation(add(sub(num(2), x()), num(1)), add(sub(x(), x()), num(1)))));
+
+        // The left of an equation needs to be more than a variable, otherwise it's an assignment
+        let script = parse_script("x = 2\n2 = x").unwrap();
+        assert!(script.get_expression(0).unwrap().get_root_node().deep_eq(&equation(num(2), x())));
+        assert!(script.to_string().starts_with("x = 2\n"));
+    }
+
 
 }
 
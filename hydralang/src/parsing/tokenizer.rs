@@ -1,15 +1,17 @@
 
 
     
-use pest::{Parser, iterators::Pairs};
+use pest::{Parser, iterators::{Pairs, Pair}, error::{Error, ErrorVariant, InputLocation}};
 use pest_derive::Parser;
-use crate::{stack::Stack, model::error::DSLError};
+use crate::{stack::Stack, model::{number::Number, error::{DSLError, SourceSpan}, operator::{OperatorTable, OperatorDef, Associativity, Arity}}};
+use super::loader::ModuleLoader;
 
 pub type TokenStream = Vec<Token>; // TODO: This may later become an actual Stream, for now performance is lower priority than simplicity
 
 #[derive(Clone, Debug)]
 pub enum Token{
     Operation(String),
+    PrefixOperation(String),
     Operand(OperandType)
 }
 
@@ -19,7 +21,11 @@ pub enum Token{
 pub enum OperandType {
     Number(String),
     Var(String),
+    Str(String),
+    Group(TokenStream),
     Vector(Vec<TokenStream>),
+    Matrix { rows: usize, cols: usize, data: Vec<TokenStream> },
+    Lambda { params: Vec<String>, body: TokenStream },
     FunctionCall{ name: String, args: Vec<TokenStream> }
 }
 
@@ -32,176 +38,608 @@ pub struct TokenFunctionDef {
     pub constraints: Vec<TokenStream>
 }
 
+// Lines of a script that aren't definitions, they're kept in order since an assignment only affects the lines after it
+pub enum TokenStatement {
+    Evaluate(TokenStream),
+    Assign(String, TokenStream),
+    Unset(String),
+    // expression is the line carrying on from "ans" ie: "* 2 + 1" is "ans * 2 + 1",
+    // written is the whole line read as an ordinary expression, only when it starts with a prefix operator ie: "-3"
+    Continue { expression: TokenStream, written: Option<TokenStream> }
+}
+
 #[derive(Parser)]
 #[grammar = "resources/grammar.pest"]
 struct Tokenizer;
 
 pub struct TokenizedScript{
     pub function_defs: Vec<TokenFunctionDef>,
-    pub expressions: Vec<TokenStream>
+    pub statements: Vec<TokenStatement>,
+    pub operators: OperatorTable,
+    pub imports: Vec<String>, // Keys of the modules imported, see ModuleLoader
+    leibniz: bool
+}
+
+// What an expression needs to know about its surroundings to be tokenized
+struct Scope<'a> {
+    operators: &'a OperatorTable,
+    leibniz: bool // Whether "dy/dx" is a derivative, scripts with lots of variables starting with d can turn this off
+}
+
+// Describes grammar rules the way a user would think about them, for telling the user what we expected to find
+fn describe_rule(rule: &Rule) -> &'static str {
+    match rule {
+        Rule::number => "a number",
+        Rule::operator => "an operator",
+        Rule::var => "a variable",
+        Rule::string => "a string",
+        Rule::vector => "a vector",
+        Rule::trailing_comma => "\",\"",
+        Rule::matrix => "a matrix",
+        Rule::lambda => "a lambda",
+        Rule::lambda_params => "lambda parameters",
+        Rule::matrix_row => "a matrix row",
+        Rule::abs_value => "an absolute value",
+        Rule::abs_expr => "an expression",
+        Rule::function_call => "a function call",
+        Rule::function_name => "a function call",
+        Rule::function_def => "a function definition",
+        Rule::function_head => "a function definition",
+        Rule::constraint => "a where clause",
+        Rule::associativity => "\"left\" or \"right\"",
+        Rule::precedence => "an operator precedence",
+        Rule::infix_decl => "an operator declaration",
+        Rule::notation_decl => "a notation declaration",
+        Rule::switch => "\"on\" or \"off\"",
+        Rule::import => "an import",
+        Rule::annotation => "an annotation",
+        Rule::expr => "an expression",
+        Rule::statement => "a statement",
+        Rule::assignment => "an assignment",
+        Rule::unset => "an unset",
+        Rule::continuation => "a statement",
+        Rule::EOI => "the end of the input",
+        _ => "something else"
+    }
 }
 
-fn parse_constraint<'a>(pairs: Pairs<'a, Rule>) -> Result<Vec<TokenStream>, DSLError> {
+fn syntax_error(pair: &Pair<Rule>, message: String) -> DSLError {
+    let span = pair.as_span();
+    DSLError::SyntaxError { message, span: SourceSpan::new(span.get_input(), span.start(), span.end()), expected: None }
+}
+
+// The error is from parsing input[offset..]
+fn from_pest_error(input: &str, offset: usize, error: Error<Rule>) -> DSLError {
+    let (start, end) = match error.location {
+        InputLocation::Pos(start) => {
+            let start = offset + start;
+            // Underline the offending word rather than just the character where things went wrong
+            let word_length = input[start..].find(char::is_whitespace).unwrap_or(input.len() - start);
+            (start, start + word_length)
+        },
+        InputLocation::Span((start, end)) => (offset + start, offset + end)
+    };
+
+    let message = if start == input.len() {
+        "Unexpected end of input".to_string()
+    } else {
+        format!("Unexpected \"{}\"", &input[start..end])
+    };
+
+    let expected = match &error.variant {
+        ErrorVariant::ParsingError { positives, .. } if !positives.is_empty() => {
+            let mut descriptions: Vec<&str> = Vec::new();
+            for description in positives.iter().map(describe_rule) {
+                if !descriptions.contains(&description) {
+                    descriptions.push(description);
+                }
+            }
+            let last = descriptions.pop().unwrap();
+            Some(if descriptions.is_empty() { last.to_string() } else { format!("{} or {}", descriptions.join(", "), last) })
+        },
+        ErrorVariant::ParsingError { .. } => None,
+        ErrorVariant::CustomError { message } => Some(message.clone())
+    };
+
+    DSLError::SyntaxError { message, span: SourceSpan::new(input, start, end), expected }
+}
+
+fn parse_constraint<'a>(pairs: Pairs<'a, Rule>, scope: &Scope) -> Result<Vec<TokenStream>, DSLError> {
     let mut constraints = Vec::new();
 
     for pair in pairs {
-        let constraint_tokens = shunting_yard(internal_tokenize(pair.into_inner())?)?;
+        let constraint_tokens = shunting_yard(internal_tokenize(pair.into_inner(), scope)?, scope.operators)?;
         constraints.push(constraint_tokens);
     }
 
     Ok(constraints)
 }
 
-// TODO: Currently this only handles statements and function_def pairs are ignored. This will need to be revisited
-pub fn tokenize_script(input: &str) -> Result<TokenizedScript, DSLError> {
-
-    let mut function_defs = Vec::new();
-    let mut token_streams = Vec::new();
+pub fn tokenize_script(input: &str, operators: &OperatorTable) -> Result<TokenizedScript, DSLError> {
+    let (script, mut errors) = tokenize_script_recovering(input, operators, &mut ModuleLoader::default());
 
-    let attempted_parse = Tokenizer::parse(Rule::script, input);
+    if errors.is_empty() { Ok(script) } else { Err(errors.remove(0)) }
+}
 
-    let parse = attempted_parse.unwrap().next().unwrap();
+/// Tokenizes everything it can, a broken line is reported and skipped rather than stopping the whole script.
+/// The errors are returned in the order they appear in the input. Imports are resolved as they are reached using loader.
+pub fn tokenize_script_recovering(input: &str, operators: &OperatorTable, loader: &mut ModuleLoader) -> (TokenizedScript, Vec<DSLError>) {
 
+    let mut script = TokenizedScript { function_defs: Vec::new(), statements: Vec::new(), operators: operators.clone(), imports: Vec::new(), leibniz: true };
+    let mut errors = Vec::new();
     let mut annotations = Vec::new();
+    let mut offset = 0;
 
-    for line in parse.into_inner() {
-        match line.as_rule() {
-            Rule::function_def => { 
-                let mut func_def_iter = line.into_inner();
-                let mut head = func_def_iter.next().unwrap().into_inner();
-                let name = head.next().unwrap().as_str().to_string();
-                let mut args = Vec::new();
-                for arg in head {
-                    let tokens = shunting_yard(internal_tokenize(arg.into_inner())?)?;
-                    args.push(tokens);
-                }
-                let statement = func_def_iter.next().unwrap();
-                let tokens = shunting_yard(internal_tokenize(statement.into_inner().next().expect("Statement without expression should be impossible").into_inner())?)?;
-                
-                let mut constraints = Vec::new();
-                if let Some(c) = func_def_iter.next() {
-                    constraints = parse_constraint(c.into_inner())?
-                }
-                
-                function_defs.push(TokenFunctionDef { name: name, args: args, tokens: tokens, annotations: annotations.clone(), constraints: constraints });
+    while offset < input.len() {
+
+        // Spans of the item are relative to where it starts parsing from, so anything reported about it has to be moved by offset
+        let item = match Tokenizer::parse(Rule::script_item, &input[offset..]) {
+            Ok(mut pairs) => pairs.next().unwrap(),
+            Err(e) => {
+                let position = offset + match e.location { InputLocation::Pos(p) => p, InputLocation::Span((p, _)) => p };
+                errors.push(from_pest_error(input, offset, e));
                 annotations.clear();
-            },
-            Rule::annotation => {
-                let var_pair = line.into_inner().next().unwrap();
-                annotations.push(match var_pair.as_rule() {
-                    Rule::var => { var_pair.as_str().to_string() },
-                    _ => return Err(DSLError::LexerError("Unable to parse annotation, wrong type provided".to_string(), None))
-                })
+                offset = next_line(input, position);
+                continue;
+            }
+        };
+
+        if item.as_rule() == Rule::EOI {
+            break;
+        }
+
+        let end = offset + item.as_span().end();
+
+        // Annotations are allowed to share a line with what they annotate, everything else has to be on a line of its own
+        if item.as_rule() != Rule::annotation {
+            if let Some((start, rest)) = rest_of_line(input, end) {
+                errors.push(DSLError::SyntaxError {
+                    message: format!("Unexpected \"{}\"", rest),
+                    span: SourceSpan::new(input, start, start + rest.len()),
+                    expected: Some("the end of the line".to_string())
+                });
+                annotations.clear();
+                offset = next_line(input, start);
+                continue;
             }
-            Rule::statement => {
-                let tokens: Vec<Token> = shunting_yard(internal_tokenize(line.into_inner().next().expect("Statement without expression should be impossible").into_inner())?)?;
-                token_streams.push(tokens);
-            },
-            _ => panic!("Unexpected rule at top level of parse tree")
         }
+
+        if let Err(e) = tokenize_script_item(item, &mut script, &mut annotations, loader) {
+            errors.push(offset_error(e, input, offset));
+            annotations.clear();
+        }
+
+        offset = end;
     }
 
-    Ok(TokenizedScript { function_defs: function_defs, expressions: token_streams })
+    (script, errors)
+}
 
-    
+fn offset_error(error: DSLError, input: &str, offset: usize) -> DSLError {
+    match error {
+        DSLError::SyntaxError { message, span, expected } => DSLError::SyntaxError { message, span: span.offset_by(input, offset), expected },
+        // Errors from inside a module stay relative to the module's own source
+        DSLError::ImportError { message, span, errors } => DSLError::ImportError { message, span: span.offset_by(input, offset), errors },
+        other => other
+    }
+}
+
+fn next_line(input: &str, position: usize) -> usize {
+    input[position..].find('\n').map(|i| position + i + 1).unwrap_or(input.len())
+}
+
+// Anything other than whitespace or a comment left on the line after position, along with where it starts
+fn rest_of_line(input: &str, position: usize) -> Option<(usize, &str)> {
+    // Items can finish after the indentation of the following line
+    if input[..position].trim_end_matches(' ').ends_with('\n') {
+        return None;
+    }
+
+    let line = &input[position..next_line(input, position)];
+    let content = line.trim_start();
+    let content = content.split('#').next().unwrap().trim_end();
+
+    if content.is_empty() {
+        None
+    } else {
+        Some((position + line.len() - line.trim_start().len(), content))
+    }
+}
+
+fn tokenize_script_item(line: Pair<Rule>, script: &mut TokenizedScript, annotations: &mut Vec<String>, loader: &mut ModuleLoader) -> Result<(), DSLError> {
+    match line.as_rule() {
+        Rule::function_def => { 
+            let mut func_def_iter = line.into_inner();
+            let mut head = func_def_iter.next().unwrap().into_inner();
+            let name = head.next().unwrap().as_str().to_string();
+            let scope = Scope { operators: &script.operators, leibniz: script.leibniz };
+
+            let mut args = Vec::new();
+            for arg in head {
+                let tokens = shunting_yard(internal_tokenize(arg.into_inner(), &scope)?, scope.operators)?;
+                args.push(tokens);
+            }
+            let statement = func_def_iter.next().unwrap();
+            let tokens = shunting_yard(internal_tokenize(statement.into_inner().next().expect("Statement without expression should be impossible").into_inner(), &scope)?, scope.operators)?;
+            
+            let mut constraints = Vec::new();
+            if let Some(c) = func_def_iter.next() {
+                constraints = parse_constraint(c.into_inner(), &scope)?
+            }
+            
+            script.function_defs.push(TokenFunctionDef { name: name, args: args, tokens: tokens, annotations: annotations.clone(), constraints: constraints });
+            annotations.clear();
+        },
+        Rule::annotation => {
+            let var_pair = line.into_inner().next().unwrap();
+            annotations.push(match var_pair.as_rule() {
+                Rule::var => { var_pair.as_str().to_string() },
+                _ => return Err(syntax_error(&var_pair, "Unable to parse annotation, wrong type provided".to_string()))
+            })
+        }
+        Rule::infix_decl => {
+            let mut decl = line.into_inner();
+            let precedence_pair = decl.next().unwrap();
+            let precedence = precedence_pair.as_str().parse().map_err(|_| syntax_error(&precedence_pair, "Invalid operator precedence".to_string()))?;
+            let associativity = match decl.next().unwrap().as_str() {
+                "right" => Associativity::Right,
+                _ => Associativity::Left
+            };
+            let symbol = decl.next().unwrap().as_str();
+
+            // Declarations only affect lines after them
+            script.operators.add(OperatorDef::new(symbol, precedence, associativity, Arity::Infix));
+        },
+        Rule::import => {
+            let span = line.as_span();
+            let name = line.into_inner().next().unwrap().as_str();
+            let key = loader.resolve(name, SourceSpan::new(span.get_input(), span.start(), span.end()))?;
+
+            // Just like declarations, operators from the module only affect lines after the import
+            if let Some(module_operators) = loader.get_operators(&key) {
+                script.operators.merge(module_operators);
+            }
+            script.imports.push(key);
+        },
+        Rule::notation_decl => {
+            let mut decl = line.into_inner();
+            let name = decl.next().unwrap();
+            let enabled = decl.next().unwrap().as_str() == "on";
+
+            // Notations are only switched for the rest of the current input, not anything that imports it or continues it
+            match name.as_str() {
+                "leibniz" => script.leibniz = enabled,
+                _ => return Err(syntax_error(&name, format!("Unknown notation \"{}\"", name.as_str())))
+            }
+        },
+        Rule::statement => {
+            let scope = Scope { operators: &script.operators, leibniz: script.leibniz };
+            let tokens: Vec<Token> = shunting_yard(internal_tokenize(line.into_inner().next().expect("Statement without expression should be impossible").into_inner(), &scope)?, scope.operators)?;
+            script.statements.push(TokenStatement::Evaluate(tokens));
+        },
+        Rule::assignment => {
+            let mut assignment = line.into_inner();
+            let name = assignment.next().unwrap().as_str().to_string();
+            let scope = Scope { operators: &script.operators, leibniz: script.leibniz };
+            let tokens = shunting_yard(internal_tokenize(assignment.next().unwrap().into_inner().next().expect("Statement without expression should be impossible").into_inner(), &scope)?, scope.operators)?;
+            script.statements.push(TokenStatement::Assign(name, tokens));
+        },
+        Rule::unset => {
+            let name = line.into_inner().next().unwrap().as_str().to_string();
+            script.statements.push(TokenStatement::Unset(name));
+        },
+        Rule::continuation => {
+            let scope = Scope { operators: &script.operators, leibniz: script.leibniz };
+            let expr = line.into_inner().next().unwrap();
+            let mut tokens = expr.clone().into_inner();
+            let op = tokens.next().unwrap().as_str().to_string();
+
+            // The rest of the line isn't a single operand, "* 2 + 1" multiplies by 2 before adding 1
+            let mut continued = vec![Token::Operand(OperandType::Var("ans".to_string())), Token::Operation(op.clone())];
+            continued.append(&mut internal_tokenize(tokens, &scope)?);
+            let expression = shunting_yard(continued, scope.operators)?;
+
+            let written = match scope.operators.prefix(&op) {
+                Some(_) => Some(shunting_yard(internal_tokenize(expr.into_inner(), &scope)?, scope.operators)?),
+                None => None
+            };
+            script.statements.push(TokenStatement::Continue { expression, written });
+        },
+        _ => return Err(syntax_error(&line, format!("Unable to handle {} at the top level of a script", describe_rule(&line.as_rule()))))
+    }
+
+    Ok(())
 }
 
 
 
 pub fn tokenize_statement(input: &str) -> Result<TokenStream, DSLError> {
 
-    let parse = Tokenizer::parse(Rule::statement, input).unwrap().next().unwrap();
+    let parse = Tokenizer::parse(Rule::single_statement, input).map_err(|e| from_pest_error(input, 0, e))?.next().unwrap();
+    let operators = &OperatorTable::default();
+    let scope = Scope { operators, leibniz: true };
 
-    shunting_yard(internal_tokenize(parse.into_inner().next().unwrap().into_inner())?)
+    shunting_yard(internal_tokenize(parse.into_inner().next().expect("Statement without expression should be impossible").into_inner(), &scope)?, operators)
 }
 
 
 // Expect pest pairs to provide a stream of Tokens, if we're at the wrong level of abstraction we'll enounter an error
-fn internal_tokenize<'a>(expression: Pairs<'a, Rule>) -> Result<TokenStream, DSLError> {
+fn internal_tokenize<'a>(expression: Pairs<'a, Rule>, scope: &Scope) -> Result<TokenStream, DSLError> {
+    let operators = scope.operators;
     let mut tokens = Vec::new();
+    let mut last_operator = None;
 
     for token in expression {
+
+        // Operands written next to each other are multiplied, "2x" is "2 * x" and "3(x + 1)" is "3 * (x + 1)"
+        if token.as_rule() != Rule::operator && matches!(tokens.last(), Some(Token::Operand(_))) {
+            if token.as_rule() == Rule::number {
+                return Err(syntax_error(&token, "Unexpected number, a number can only be multiplied by writing it first ie: \"2x\"".to_string()));
+            }
+            tokens.push(Token::Operation("*".to_string()));
+        }
+
         match token.as_rule() {
             Rule::number => {
+                // The grammar should only let through numbers we can read, this makes sure of it while we still know where the number is
+                Number::parse(token.as_str()).map_err(|_| syntax_error(&token, format!("Invalid number \"{}\"", token.as_str())))?;
                 tokens.push(Token::Operand(OperandType::Number(token.as_str().to_string())))
             },
+            Rule::string => {
+                let quoted = token.as_str();
+                tokens.push(Token::Operand(OperandType::Str(unescape(&quoted[1..quoted.len() - 1]))))
+            },
             Rule::operator => {
-                tokens.push(Token::Operation(token.as_str().to_string()))
+                // An operator with nothing to its left (start of expression or directly after another operator) can only be a prefix operator
+                let follows_operand = matches!(tokens.last(), Some(Token::Operand(_)));
+                let mut split = split_operators(token.as_str(), follows_operand, operators).ok_or_else(||
+                    syntax_error(&token, format!("Unable to make sense of operation \"{}\"", token.as_str()))
+                )?;
+                tokens.append(&mut split);
+                last_operator = Some(token);
             },
             Rule::vector => {
 
                 let mut vec_tokens = Vec::new();
+                let mut trailing_comma = false;
 
                 // Vectors have internal expressions that need to be tokenized and parsed
                 for expr in token.into_inner() {
-                    vec_tokens.push(shunting_yard(internal_tokenize(expr.into_inner())?)?)
+                    match expr.as_rule() {
+                        Rule::trailing_comma => trailing_comma = true,
+                        _ => vec_tokens.push(shunting_yard(internal_tokenize(expr.into_inner(), scope)?, operators)?)
+                    }
                 }
 
-                let operand = Token::Operand(OperandType::Vector(vec_tokens));
+                let operand = if vec_tokens.len() == 1 && !trailing_comma {
+                    Token::Operand(OperandType::Group(vec_tokens.remove(0)))
+                } else {
+                    Token::Operand(OperandType::Vector(vec_tokens))
+                };
                 tokens.push(operand);
             },
             Rule::var => {
                 tokens.push(Token::Operand(OperandType::Var(token.as_str().to_string())))
             },
-            Rule::function_call => {
-                // TODO: Implement function calls
+            Rule::lambda => {
+                let mut lambda = token.into_inner();
+                let params_pair = lambda.next().unwrap();
+                let params = params_pair.clone().into_inner().map(|p| p.as_str().to_string()).collect::<Vec<String>>();
+
+                if let Some(duplicate) = params.iter().enumerate().find(|(i, p)| params[..*i].contains(p)).map(|(_, p)| p) {
+                    return Err(syntax_error(&params_pair, format!("Lambda parameter \"{}\" is declared more than once", duplicate)));
+                }
 
+                let body = shunting_yard(internal_tokenize(lambda.next().unwrap().into_inner(), scope)?, operators)?;
+                tokens.push(Token::Operand(OperandType::Lambda { params, body }));
+            },
+            Rule::matrix => {
+                let mut data = Vec::new();
+                let mut rows = 0;
+                let mut cols = 0;
+
+                for row in token.into_inner() {
+                    let row_pair = row.clone();
+                    let mut entries = 0;
+
+                    for expr in row.into_inner() {
+                        data.push(shunting_yard(internal_tokenize(expr.into_inner(), scope)?, operators)?);
+                        entries += 1;
+                    }
+
+                    // Every row has to be the same length as the first
+                    if rows == 0 {
+                        cols = entries;
+                    } else if entries != cols {
+                        return Err(syntax_error(&row_pair, format!("Matrix row {} has {} entries but the rows before it have {}", rows + 1, entries, cols)));
+                    }
+                    rows += 1;
+                }
+
+                tokens.push(Token::Operand(OperandType::Matrix { rows, cols, data }));
+            },
+            Rule::abs_value => {
+                // |x| is just another way of writing abs(x)
+                let expr = token.into_inner().next().unwrap();
+                let args = vec![shunting_yard(internal_tokenize(expr.into_inner(), scope)?, operators)?];
+                tokens.push(Token::Operand(OperandType::FunctionCall { name: "abs".to_string(), args }));
+            },
+            Rule::function_call => {
                 let mut function_call = token.into_inner();
                 let name = function_call.next().unwrap().as_str();
 
                 let mut args = Vec::new();
 
                 for arg in function_call {
-                    args.push(shunting_yard(internal_tokenize(arg.into_inner())?)?);
+                    args.push(shunting_yard(internal_tokenize(arg.into_inner(), scope)?, operators)?);
                 }
-                
+
+                // Whether "t(4, 2, 0)" is a call or multiplication depends on what t turns out to be, see VariableReplacer
                 tokens.push(Token::Operand(OperandType::FunctionCall { name: name.to_string() , args }));
             }
-            _ => panic!("Unexpected token provided")
+            _ => return Err(syntax_error(&token, format!("Unexpected {}", describe_rule(&token.as_rule()))))
         }
     }
 
+    // The grammar is happy for an expression to end in an operator, we catch it here while we still know where it is
+    if let (Some(Token::Operation(op) | Token::PrefixOperation(op)), Some(pair)) = (tokens.last(), last_operator) {
+        return Err(syntax_error(&pair, format!("Operation \"{}\" does not have anything on its right to operate on", op)));
+    }
+
+    if scope.leibniz {
+        tokens = desugar_leibniz(tokens);
+    }
+
     Ok(tokens)
 }
 
-// TODO: At some point this needs to be made more universal, but for now we'll deal with the repetition and string comparisons
-pub fn op_precedence(op: &str) -> usize {
-    match op {
-        "^" => 4,
-        "*" => 3,
-        "/" => 3,
-        "-" => 2,
-        "+" => 2,
-        "none" => 0, // None is specifically reserved for when the stack of operators is empty, it's not a real operator
-        _ => 1
+// Leibniz notation is rewritten into calls to df before operator precedence gets involved so that "2 dy/dx" is "2 * df(y, x)" rather than "(2 * dy) / dx"
+fn desugar_leibniz(tokens: TokenStream) -> TokenStream {
+    let mut result = Vec::new();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let derivative = match (tokens.get(i), tokens.get(i + 1), tokens.get(i + 2)) {
+            (Some(Token::Operand(numerator)), Some(Token::Operation(op)), Some(Token::Operand(denominator))) if op == "/" => leibniz_derivative(numerator, denominator),
+            _ => None
+        };
+
+        match derivative {
+            Some(call) => {
+                result.push(Token::Operand(call));
+                i += 3;
+            },
+            None => {
+                result.push(tokens[i].clone());
+                i += 1;
+            }
+        }
+    }
+
+    result
+}
+
+// The grammar only lets through the escapes we know about, so anything after a backslash is one of them
+fn unescape(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        result.push(match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some(escaped) => escaped,
+                None => c
+            },
+            c => c
+        });
+    }
+
+    result
+}
+
+// Matches "dy/dx", "d(expr)/dx" and "d/dx(expr)"
+fn leibniz_derivative(numerator: &OperandType, denominator: &OperandType) -> Option<OperandType> {
+    // "dx" is a differential with respect to x, just "d" on its own is not
+    fn differential(name: &str) -> Option<&str> {
+        name.strip_prefix('d').filter(|rest| !rest.is_empty())
+    }
+
+    let var = |name: &str| vec![Token::Operand(OperandType::Var(name.to_string()))];
+
+    let (expression, with_respect_to) = match (numerator, denominator) {
+        (OperandType::Var(y), OperandType::Var(x)) => (var(differential(y)?), differential(x)?),
+        (OperandType::FunctionCall { name, args }, OperandType::Var(x)) if name == "d" && args.len() == 1 => (args[0].clone(), differential(x)?),
+        (OperandType::Var(d), OperandType::FunctionCall { name, args }) if d == "d" && args.len() == 1 => (args[0].clone(), differential(name)?),
+        _ => return None
+    };
+
+    Some(OperandType::FunctionCall { name: "df".to_string(), args: vec![expression, var(with_respect_to)] })
+}
+
+// Operators are lexed as runs of symbol characters so "2*-3" arrives as "*-", we split them back up into the longest declared operators we can find.
+// After an operand the run starts with one infix operator (which doesn't need to be declared), everything after that must be prefix operators.
+fn split_operators(run: &str, follows_operand: bool, operators: &OperatorTable) -> Option<TokenStream> {
+
+    // Every position a multi character run could be cut at, longest first
+    fn cut_points(run: &str) -> Vec<usize> {
+        (1..=run.len()).rev().filter(|i| run.is_char_boundary(*i)).collect()
+    }
+
+    fn split_prefix(run: &str, operators: &OperatorTable) -> Option<TokenStream> {
+        if run.is_empty() {
+            return Some(Vec::new());
+        }
+
+        for end in cut_points(run) {
+            if operators.prefix(&run[..end]).is_none() { continue; }
+
+            if let Some(mut rest) = split_prefix(&run[end..], operators) {
+                rest.insert(0, Token::PrefixOperation(run[..end].to_string()));
+                return Some(rest);
+            }
+        }
+
+        None
+    }
+
+    if !follows_operand {
+        return split_prefix(run, operators);
+    }
+
+    // Prefer declared infix operators, otherwise fall back on the longest undeclared one
+    for declared_only in [true, false] {
+        for end in cut_points(run) {
+            if declared_only && operators.infix(&run[..end]).is_none() { continue; }
+
+            if let Some(mut rest) = split_prefix(&run[end..], operators) {
+                rest.insert(0, Token::Operation(run[..end].to_string()));
+                return Some(rest);
+            }
+        }
+    }
+
+    None
+}
+
+fn token_precedence(token: &Token, operators: &OperatorTable) -> usize {
+    match token {
+        Token::Operation(op) => operators.infix_or_default(op.as_str()).get_precedence(),
+        Token::PrefixOperation(op) => operators.prefix(op.as_str()).map(|o| o.get_precedence()).unwrap_or(0),
+        Token::Operand(_) => 0
     }
 }
 
-pub fn shunting_yard(tokens: TokenStream) -> Result<TokenStream, DSLError> {
+pub fn shunting_yard(tokens: TokenStream, operators: &OperatorTable) -> Result<TokenStream, DSLError> {
 
     let mut result = Vec::new();
-    let mut operators = Stack::<Token>::new();
+    let mut operator_stack = Stack::<Token>::new();
     
     for token in tokens {
         match token {
             Token::Operation(s) => {
 
-                let precedence = op_precedence(s.as_str());
+                let operator = operators.infix_or_default(s.as_str());
 
-                loop {
-                    let stack_precedence = op_precedence(operators.peek().map(|t| t.as_operation() ).unwrap_or(&"none"));
-                    if stack_precedence < precedence { break; }
+                // Operations already on the stack that bind tighter get applied first, for operations of equal precedence associativity breaks the tie
+                while let Some(stack_op) = operator_stack.peek() {
+                    let stack_precedence = token_precedence(stack_op, operators);
+                    let applies_first = stack_precedence > operator.get_precedence() ||
+                        (stack_precedence == operator.get_precedence() && operator.get_associativity() == Associativity::Left);
 
-                    let stack_op = operators.pop().ok_or(
-                        DSLError::LexerError(format!("Parsing Error, operation \"{}\" does not have enough operands",
-                            operators.peek().map(|t| t.as_operation() 
-                        ).unwrap_or(&"none")), None))?;
-                    
-                    result.push(stack_op);
+                    if !applies_first { break; }
 
+                    result.push(operator_stack.pop().unwrap());
                 }
 
-                operators.push(Token::Operation(s));
+                operator_stack.push(Token::Operation(s));
+
+            },
+            Token::PrefixOperation(s) => {
+
+                // Prefix operations apply to whatever comes after them, so there is nothing on the stack for them to take yet
+                operator_stack.push(Token::PrefixOperation(s));
 
             },
             Token::Operand(t) => {
@@ -212,8 +650,8 @@ pub fn shunting_yard(tokens: TokenStream) -> Result<TokenStream, DSLError> {
         }
     }
 
-    while operators.len() != 0 {
-        result.push(operators.pop().unwrap());
+    while operator_stack.len() != 0 {
+        result.push(operator_stack.pop().unwrap());
     }
 
     Ok(result)
@@ -225,13 +663,14 @@ impl Token {
     pub fn as_operation(&self) -> &str {
         match self {
             Token::Operation(op) => op.as_str(),
+            Token::PrefixOperation(op) => op.as_str(),
             Token::Operand(_) => panic!("Attempted to unwrap token as an operation when it is not an operation"),
         }
     }
 
     pub fn as_operand(&self) -> &OperandType {
         match self {
-            Token::Operation(_) => panic!("Attempted to unwrap token as an operand when it is not an operand"),
+            Token::Operation(_) | Token::PrefixOperation(_) => panic!("Attempted to unwrap token as an operand when it is not an operand"),
             Token::Operand(op) => op
         }
     }
@@ -250,8 +689,10 @@ impl Token {
 #[cfg(test)]
 mod tests {
 
-    use crate::{model::expression_builder::*, parsing::tokenizer::{Token, OperandType, tokenize_statement}, traits::DeepEq};
+    use crate::{model::{expression_builder::*, expression::Node, number::Number, error::DSLError, operator::OperatorTable}, parsing::tokenizer::{Token, OperandType, TokenStatement, tokenize_statement, tokenize_script}, traits::DeepEq};
     use crate::parsing::parser::parse_statement;
+    use crate::visitor::ExpressionModfierVisitor;
+    use crate::testing::Random;
 
     #[test]
     fn test_shunting_yard() {
@@ -263,7 +704,7 @@ mod tests {
         for token in tokens.clone() {
             match token {
                 Token::Operand(op) => print!("{:?}", op),
-                Token::Operation(t) => print!("({})", t)
+                Token::Operation(t) | Token::PrefixOperation(t) => print!("({})", t)
             }
             
         }
@@ -302,7 +743,7 @@ mod tests {
                         for token in token {
                             match token {
                                 Token::Operand(op) => print!("{:?}", op),
-                                Token::Operation(t) => print!("({})", t)
+                                Token::Operation(t) | Token::PrefixOperation(t) => print!("({})", t)
                             }
                             
                         }
@@ -310,7 +751,7 @@ mod tests {
                     }
                 }
                 Token::Operand(op) => print!("{:?}", op),
-                Token::Operation(t) => print!("({})", t)
+                Token::Operation(t) | Token::PrefixOperation(t) => print!("({})", t)
             }
             
         }
@@ -324,7 +765,7 @@ mod tests {
         let test = "5 + 10 / 20 - 4 + a"; // Should be 5, 10, 20, /, +, 4 - a +
         let result = parse_statement(test).unwrap().get_root_node().clone();
 
-        let expected = add(sub(add(num(5), div(num(10), num(20))),num(4)),var("a".to_string()));
+        let expected = add(sub(add(num(5), fraction(10, 20)),num(4)),var("a".to_string()));
 
 
         assert!(result.deep_eq(&expected));
@@ -344,14 +785,312 @@ mod tests {
 
     }
 
-    // TODO: I Don't currently care enough about this issue to fix it
+    #[test]
     fn test_negation_parsing() {
-        let _test = "10 + -5"; // We somehow need to identify this negative as an LOP or else we would get an error
+        let test = "10 + -5";
+        let result = parse_statement(test).unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&add(num(10), num(-5))));
+
+        let test2 = "-1 -5 - -6"; // Weird AF but technically a valid expression and should be handled as expected
+        let result2 = parse_statement(test2).unwrap().get_root_node().clone();
+        assert!(result2.deep_eq(&sub(sub(num(-1), num(5)), num(-6))));
+
+        let test3 = "-x^2 * 2";
+        let result3 = parse_statement(test3).unwrap().get_root_node().clone();
+        assert!(result3.deep_eq(&mul(neg(pow(var("x".to_string()), num(2))), num(2))));
+
+        let test4 = "!a";
+        let result4 = parse_statement(test4).unwrap().get_root_node().clone();
+        assert!(result4.deep_eq(&lop("!", var("a".to_string()))));
+
+        let test5 = "2*-3";
+        let result5 = parse_statement(test5).unwrap().get_root_node().clone();
+        assert!(result5.deep_eq(&mul(num(2), num(-3))));
+
+        // Brackets keep it a negation rather than a negative number
+        let result6 = parse_statement("-(3)").unwrap().get_root_node().clone();
+        assert!(result6.deep_eq(&neg(num(3))));
+
+        assert!(parse_statement("* 2").is_err());
+    }
+
+    #[test]
+    fn test_implicit_multiplication_parsing() {
+        let x = || var("x".to_string());
+
+        let result = parse_statement("2x^2").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&mul(num(2), pow(x(), num(2)))));
+
+        let result = parse_statement("1 / 2x").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&mul(fraction(1, 2), x())));
+
+        let result = parse_statement("y / 2x").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&mul(div(var("y".to_string()), num(2)), x())));
+
+        let result = parse_statement("(x + 1)(x - 1)").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&mul(add(x(), num(1)), sub(x(), num(1)))));
+
+        // Outside of a function nothing is known to be a variable, so this is a call
+        let result = parse_statement("t(4, 2, 0)").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&func_call("t".to_string(), vec![num(4), num(2), num(0)])));
+
+        assert!(parse_statement("x 2").is_err());
+    }
+
+    #[test]
+    fn test_leibniz_notation() {
+        let x = || var("x".to_string());
+        let df = |e: Node| func_call("df".to_string(), vec![e, x()]);
+
+        let result = parse_statement("dy/dx").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&df(var("y".to_string()))));
+
+        let result = parse_statement("2 dy/dx").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&mul(num(2), df(var("y".to_string())))));
+
+        let result = parse_statement("d/dx(x^2 + 1)").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&df(add(pow(x(), num(2)), num(1)))));
+
+        let result = parse_statement("d(x^2)/dx").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&df(pow(x(), num(2)))));
+
+        // A lone d isn't a differential
+        let result = parse_statement("d/x").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&div(var("d".to_string()), x())));
+
+        let script = tokenize_script("dy/dx\nnotation leibniz off\ndy/dx", &OperatorTable::default()).unwrap();
+        assert!(matches!(&script.statements[0], TokenStatement::Evaluate(t) if matches!(t.as_slice(), [Token::Operand(OperandType::FunctionCall { .. })])));
+        assert!(matches!(&script.statements[1], TokenStatement::Evaluate(t) if matches!(t.as_slice(), [Token::Operand(_), Token::Operand(_), Token::Operation(_)])));
+
+        assert!(tokenize_script("notation polish on", &OperatorTable::default()).is_err());
+    }
+
+    #[test]
+    fn test_absolute_value_parsing() {
+        let abs = |e: Node| func_call("abs".to_string(), vec![e]);
+        let a = || var("a".to_string());
+        let b = || var("b".to_string());
+
+        let result = parse_statement("|a| + |b|").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&add(abs(a()), abs(b()))));
 
-        let _test2 = "-1 -5 - -6"; // Weird AF but technically a valid expression and should be handled as expected
+        let result = parse_statement("||a| - |-b||").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&abs(sub(abs(a()), abs(neg(b()))))));
 
+        let result = parse_statement("2|a - b|^2").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&mul(num(2), pow(abs(sub(a(), b())), num(2)))));
 
+        assert!(parse_statement("|a + b").is_err());
+    }
+
+    #[test]
+    fn test_matrix_parsing() {
+        let script = tokenize_script("[ 1, 2, 3
+            4, 5, 6
+            7, 8, 9 ]
+            [1, x; 2x, 4]
+            f(m) = [m, 0
+                    0, m]", &OperatorTable::default()).unwrap();
+
+        assert_eq!(script.statements.len(), 2);
+        assert_eq!(script.function_defs.len(), 1);
+        assert!(matches!(&script.statements[0], TokenStatement::Evaluate(t) if matches!(t.as_slice(), [Token::Operand(OperandType::Matrix { rows: 3, cols: 3, .. })])));
+
+        let result = parse_statement("[1, x; 2x, 4]").unwrap().get_root_node().clone();
+        let x = || var("x".to_string());
+        assert!(result.deep_eq(&matrix(2, 2, vec![num(1), x(), mul(num(2), x()), num(4)])));
+
+        match tokenize_script("[1, 2, 3\n4, 5]", &OperatorTable::default()) {
+            Err(DSLError::SyntaxError { message, span, .. }) => {
+                assert_eq!(message, "Matrix row 2 has 2 entries but the rows before it have 3");
+                assert_eq!((span.line, span.column), (2, 1));
+            },
+            _ => panic!("Expected a syntax error for a ragged matrix")
+        }
+    }
+
+    #[test]
+    fn test_lambda_parsing() {
+        let x = || var("x".to_string());
+
+        let result = parse_statement("(x) => x^2 + 1").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&lambda(&["x"], add(pow(x(), num(2)), num(1)))));
+
+        let result = parse_statement("map(v, x => 2x)").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&func_call("map".to_string(), vec![var("v".to_string()), lambda(&["x"], mul(num(2), x()))])));
+
+        let result = parse_statement("() => 1").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&lambda(&[], num(1))));
+
+        assert!(parse_statement("(x, x) => x").is_err());
+        assert!(parse_statement("(x) =>").is_err());
+    }
 
+    #[test]
+    fn test_matrix_printing() {
+        let parsed = parse_statement("[1, 200; -3, x + 1]").unwrap();
+        assert_eq!(parsed.to_string(), "[ 1,   200\n -3, x + 1]");
+
+        let reparsed = parse_statement(parsed.to_string().as_str()).unwrap();
+        assert!(parsed.deep_eq(&reparsed));
+    }
+
+    #[test]
+    fn test_syntax_error_spans() {
+        let result = tokenize_script("f(x) = x^2\ng(x) = )", &OperatorTable::default());
+
+        match result {
+            Err(DSLError::SyntaxError { message, span, expected }) => {
+                assert_eq!(message, "Unexpected \")\"");
+                assert_eq!((span.line, span.column, span.text.as_str()), (2, 8, ")"));
+                assert!(expected.is_some());
+            },
+            _ => panic!("Expected a syntax error")
+        }
+
+        match tokenize_statement("(1, 2") {
+            Err(DSLError::SyntaxError { message, span, .. }) => {
+                assert_eq!(message, "Unexpected end of input");
+                assert_eq!((span.line, span.column), (1, 6));
+            },
+            _ => panic!("Expected a syntax error")
+        }
+
+        match tokenize_statement("10 * x +") {
+            Err(e @ DSLError::SyntaxError { .. }) => {
+                assert!(e.to_string().ends_with("1 | 10 * x +\n  |        ^"), "{}", e);
+            },
+            _ => panic!("Expected a syntax error")
+        }
+    }
+
+    #[test]
+    fn test_right_associative_pow() {
+        let result = parse_statement("2^3^2").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&pow(num(2), pow(num(3), num(2)))));
+
+        let result2 = parse_statement("a - b - c").unwrap().get_root_node().clone();
+        assert!(result2.deep_eq(&sub(sub(var("a".to_string()), var("b".to_string())), var("c".to_string()))));
+    }
+
+    #[test]
+    fn test_number_literals() {
+        let parse = |input: &str| parse_statement(input).unwrap().get_root_node().clone();
+
+        for (input, expected) in [("1_000_000", 1000000), ("50 000", 50000), ("1 234 567", 1234567), ("0xFF", 255), ("0b1010", 10), ("0xff_ff", 65535), ("2e3", 2000)] {
+            assert!(matches!(parse(input), Node::Num(Number::Int(n)) if n == expected), "{} parsed as {}", input, parse(input).to_string());
+        }
+
+        assert!(matches!(parse("6.022e23"), Node::Num(Number::Decimal(_))));
+        assert!(matches!(parse("6.022e23"), Node::Num(n) if n == Number::parse("602200000000000000000000").unwrap()));
+        assert!(matches!(parse("1.5E-3"), Node::Num(Number::Decimal(_))));
+        assert!(matches!(parse("0x1_0000_0000_0000_0000"), Node::Num(Number::BigInt(_))));
+        assert!(matches!(parse("1e30"), Node::Num(n) if n.to_string() == "1000000000000000000000000000000"));
+        assert!(matches!(parse("1e100000"), Node::Num(Number::BigInt(_))));
+        // Too big to be worth writing out in full
+        assert!(matches!(parse("1e100000000"), Node::Num(Number::Decimal(_))));
+        assert_eq!(parse("-25e100000000").to_string(), "-25e100000000");
+        assert_eq!(parse("1e-100000000").to_string(), "1e-100000000");
+
+        // Spaces only group digits in threes, anything else is still two numbers next to each other
+        assert!(parse_statement("50 00").is_err());
+        assert!(parse("2 300x").deep_eq(&mul(num(2300), var("x".to_string()))));
+        assert!(parse("0x").deep_eq(&mul(num(0), var("x".to_string()))));
+        assert!(parse("2e").deep_eq(&mul(num(2), var("e".to_string()))));
+
+        assert!(Number::parse("1.2.3").is_err());
+        assert!(Number::parse("0b").is_err());
+    }
+
+    #[test]
+    fn test_string_parsing() {
+        let result = parse_statement("concat(\"Δt = \", \"say \\\"hi\\\"\\n\") + \"#not a comment\"").unwrap();
+        assert!(result.get_root_node().deep_eq(&add(func_call("concat".to_string(), vec![string("Δt = "), string("say \"hi\"\n")]), string("#not a comment"))));
+        assert_eq!(result.to_string(), "concat(\"Δt = \", \"say \\\"hi\\\"\\n\") + \"#not a comment\"");
+
+        assert!(parse_statement("\"unclosed").is_err());
+        assert!(parse_statement("\"bad \\q escape\"").is_err());
+    }
+
+    #[test]
+    fn test_identifiers() {
+        let v = |s: &str| var(s.to_string());
+
+        let result = parse_statement("vec3(1, 2, 3) + log10(f2(x_1))").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&add(func_call("vec3".to_string(), vec![num(1), num(2), num(3)]), func_call("log10".to_string(), vec![func_call("f2".to_string(), vec![v("x_1")])]))));
+
+        let result = parse_statement("θ + ω_0 * Δt - _tmp").unwrap();
+        assert!(result.get_root_node().deep_eq(&sub(add(v("θ"), mul(v("ω_0"), v("Δt"))), v("_tmp"))));
+        assert_eq!(result.to_string(), "θ + ω_0 * Δt - _tmp");
+
+        // Only "where" on its own is reserved, and a name can't start with a digit so "2x" is still multiplication
+        let result = parse_statement("wherever + 2x").unwrap().get_root_node().clone();
+        assert!(result.deep_eq(&add(v("wherever"), mul(num(2), v("x")))));
+    }
+
+    #[test]
+    fn test_printing_matches_parsing() {
+        for test in ["(2^3)^2", "2^3^2", "a - (b - c)", "a - b - c", "a / (b * c)", "(-a)^2", "-a^2", "a % b * c", "|a| * |-b|", "||a| - |b||", "(x, y) => x + y", "f((x) => x^2, 2 * ((y) => y))", "a + b = c * d", "(a = b) = c", "f(a = (x) => x)", "1/3", "x + 1/2", "-1/3", "(1) / 3", "-(1/3)", "(1/3)^2", "2^(1/2)", "1/2 / 3", "a / (1/3)"] {
+            let parsed = parse_statement(test).unwrap();
+            let reparsed = parse_statement(parsed.to_string().as_str()).unwrap();
+            assert!(parsed.deep_eq(&reparsed), "{} printed as {}", test, parsed.to_string());
+        }
+
+        // Whole number literals divided by a positive one are a fraction, brackets keep it a division
+        assert!(parse_statement("-1/3").unwrap().get_root_node().deep_eq(&fraction(-1, 3)));
+        assert!(parse_statement("(1) / 3").unwrap().get_root_node().deep_eq(&div(num(1), num(3))));
+        assert!(parse_statement("1 / -3").unwrap().get_root_node().deep_eq(&div(num(1), num(-3))));
+        assert_eq!(div(num(1), num(3)).to_string(), "(1) / 3");
+
+        // Floats that aren't finite can't be written as a number, see Node::to_string_with
+        for (f, printed) in [(f64::NAN, "NaN"), (f64::INFINITY, "inf"), (f64::NEG_INFINITY, "-inf")] {
+            assert_eq!(float(f).to_string(), printed);
+        }
+    }
+
+    fn random_node(random: &mut Random, depth: usize) -> Node {
+        let leaf = depth == 0 || random.next(3) == 0;
+        match if leaf { random.next(4) } else { 4 + random.next(7) } {
+            0 => match random.next(4) {
+                0 => fraction(random.next(2000) as i64 - 1000, 2 + random.next(50) as i64),
+                1 => Node::Num(Number::parse(&format!("{}{}", random.pick(&["", "-"]), "9".repeat(19 + random.next(20)))).unwrap()),
+                _ => num(random.next(2000) as i64 - 1000)
+            },
+            1 => Node::Num(Number::parse(&format!("{}.{}", random.next(100) as i64 - 50, random.next(1000))).unwrap()),
+            2 => float((random.next(20000) as f64 - 10000.0) / 64.0),
+            3 if random.next(4) == 0 => string(random.pick(&["", "a b", "Δt", "\"quoted\"", "back\\slash", "new\nline\t"])),
+            3 => var(random.pick(&["x", "y", "a", "b", "d", "dx", "dy", "delta", "x_1", "θ", "Δt"]).to_string()),
+            4 | 5 => op(random.pick(&["+", "-", "*", "/", "^", "%", "==", "<", ">=", "!=", "$"]), random_node(random, depth - 1), random_node(random, depth - 1)),
+            6 => lop(random.pick(&["-", "!"]), random_node(random, depth - 1)),
+            7 => vector((0..1 + random.next(3)).map(|_| random_node(random, depth - 1)).collect()),
+            8 => {
+                let (rows, cols) = (1 + random.next(2), 1 + random.next(3));
+                matrix(rows, cols, (0..rows * cols).map(|_| random_node(random, depth - 1)).collect())
+            },
+            9 => {
+                let params = ["x", "y", "z"][..random.next(4)].to_vec();
+                if random.next(2) == 0 { lambda(&params, random_node(random, depth - 1)) } else { equation(random_node(random, depth - 1), random_node(random, depth - 1)) }
+            },
+            _ => func_call(random.pick(&["abs", "d", "dx", "f", "_g"]).to_string(), (0..1 + random.next(3)).map(|_| random_node(random, depth - 1)).collect())
+        }
+    }
+
+    // Floats are printed like any other decimal so they can only read back as a number with the same value
+    struct FloatsAsNumbers;
+
+    impl ExpressionModfierVisitor for FloatsAsNumbers {
+        fn visit_float(&mut self, n: f64) -> Node { Node::Num(Number::parse(&format!("{:?}", n)).unwrap()) }
+    }
+
+    #[test]
+    fn test_printing_round_trips() {
+        let mut random = Random(0x2545F4914F6CDD1D);
+        for _ in 0..2000 {
+            let node = random_node(&mut random, 4);
+            let printed = node.to_string();
+            let reparsed = parse_statement(printed.as_str()).unwrap_or_else(|e| panic!("{} failed to parse: {}", printed, e));
+            assert!(reparsed.get_root_node().deep_eq(&FloatsAsNumbers.visit_node(node.clone())), "{} parsed back as {}\n{:?}", printed, reparsed.to_string(), node);
+        }
     }
 
 
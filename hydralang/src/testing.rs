@@ -0,0 +1,22 @@
+// Helpers shared between the tests of different modules
+
+/// Small xorshift generator so anything random in a test is the same on every run
+pub struct Random(pub u64);
+
+impl Random {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Somewhere from 0 up to but not including bound
+    pub fn next(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    pub fn pick<'a>(&mut self, options: &[&'a str]) -> &'a str {
+        options[self.next(options.len())]
+    }
+}
@@ -1,9 +1,11 @@
-use crate::model::{expression::Node, Expression, symbol_table::SymbolTable};
+use crate::model::{expression::Node, Expression, Script, symbol_table::SymbolTable, operator::OperatorTable};
 
 
 pub trait Callable: ToString {
 
-    fn call(&self, symbol_table: SymbolTable) -> Expression;
+    fn call(&self, symbol_table: SymbolTable, script: &Script) -> Expression;
+
+    fn to_string_with(&self, _operators: &OperatorTable) -> String { self.to_string() }
 
 }
 
@@ -33,7 +35,11 @@ impl DeepEq for Node {
         match (self, other) {
             (Num(a), Num(b)) => a == b,
             (Var(a), Var(b)) => a == b,
+            (Str(a), Str(b)) => a == b,
             (Vector(v1), Vector(v2)) => v1.len() == v2.len() && v1.iter().zip(v2.iter()).all(|(a, b)| a.deep_eq(b)),
+            (Matrix { rows: r1, cols: c1, data: d1 }, Matrix { rows: r2, cols: c2, data: d2 }) => r1 == r2 && c1 == c2 && d1.iter().zip(d2.iter()).all(|(a, b)| a.deep_eq(b)),
+            (Lambda { params: p1, body: b1 }, Lambda { params: p2, body: b2 }) => p1 == p2 && b1.deep_eq(b2),
+            (Equation(l1, r1), Equation(l2, r2)) => l1.deep_eq(l2) && r1.deep_eq(r2),
             (Op(s1, a1, a2), Op(s2, b1, b2)) => s1 == s2 && a1.deep_eq(b1) && a2.deep_eq(b2),
             (LOp(s1, a), LOp(s2, b)) => s1 == s2 && a.deep_eq(b),
             (Float(f1), Float(f2)) => f1 == f2,
@@ -52,7 +58,11 @@ impl ShallowEq for Node {
         match (self, other) {
             (Num(_), Num(_)) => true,
             (Var(_), Var(_)) => true,
+            (Str(_), Str(_)) => true,
             (Vector(_), Vector(_)) => true,
+            (Matrix { rows: r1, cols: c1, .. }, Matrix { rows: r2, cols: c2, .. }) => r1 == r2 && c1 == c2,
+            (Lambda { params: p1, .. }, Lambda { params: p2, .. }) => p1.len() == p2.len(),
+            (Equation(_, _), Equation(_, _)) => true,
             (Op(s1, _, _), Op(s2, _, _)) => s1 == s2,
             (LOp(s1, _), LOp(s2, _)) => s1 == s2,
             (Float(_), Float(_)) => true,
@@ -36,11 +36,26 @@ impl<'a, State: Default + Clone> TreeStructure<'a, State> {
             Node::Num(_) =>   TraversalStructuralNode { children: Box::new([]), reference: &node, state_ref: state_table.len() - 1 },
             Node::Float(_) => TraversalStructuralNode { children: Box::new([]), reference: &node, state_ref: state_table.len() - 1 },
             Node::Var(_) => TraversalStructuralNode { children: Box::new([]), reference: &node, state_ref: state_table.len() - 1 } ,
+            Node::Str(_) => TraversalStructuralNode { children: Box::new([]), reference: node, state_ref: state_table.len() - 1 },
             Node::Vector(args) => {
                 let args_converted = args.iter().map(|node| Self::build_traversal_tree(node, state_table)).collect::<Vec<TraversalStructuralNode<'a>>>();
                 let result = TraversalStructuralNode { children: args_converted.into_boxed_slice(), reference: &node, state_ref: state_table.len() - 1 };
                 result
             },
+            Node::Matrix { data, .. } => {
+                let data_converted = data.iter().map(|node| Self::build_traversal_tree(node, state_table)).collect::<Vec<TraversalStructuralNode<'a>>>();
+                TraversalStructuralNode { children: data_converted.into_boxed_slice(), reference: node, state_ref: state_table.len() - 1 }
+            },
+            Node::Equation(l, r) => TraversalStructuralNode {
+                children: vec![Self::build_traversal_tree(l, state_table), Self::build_traversal_tree(r, state_table)].into_boxed_slice(),
+                reference: node,
+                state_ref: state_table.len() - 1
+            },
+            Node::Lambda { body, .. } => TraversalStructuralNode {
+                children: vec![Self::build_traversal_tree(body, state_table)].into_boxed_slice(),
+                reference: node,
+                state_ref: state_table.len() - 1
+            },
             Node::FunctionCall { name:_, args } => {
                 let args_converted = args.iter().map(|node| Self::build_traversal_tree(node, state_table)).collect::<Vec<TraversalStructuralNode<'a>>>();
                 let result = TraversalStructuralNode { children: args_converted.into_boxed_slice(), reference: &node, state_ref: state_table.len() - 1 };
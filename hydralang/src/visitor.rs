@@ -1,4 +1,6 @@
-use crate::{model::{Expression, expression::Node, Script, symbol_table::SymbolTable, number::Number}, traits::DeepEq};
+use std::collections::HashSet;
+
+use crate::{model::{Expression, expression::Node, Script, symbol_table::SymbolTable, number::Number, error::DSLError}, traits::DeepEq};
 
 // TODO: Currently expressions are immutable and need to be completely rebuilt to be modified. This makes sense for now and helps avoid many bugs, but optimisations are possible that have not been implemneted
 // This is a basic left side, depth first traversal with no modifications made
@@ -13,7 +15,11 @@ pub trait ExpressionModfierVisitor {
             Node::Num(n) => self.visit_num(n),
             Node::Float(n) => self.visit_float(n),
             Node::Var(name) => self.visit_var(name),
+            Node::Str(s) => self.visit_str(s),
             Node::Vector(v) => self.visit_vec(v),
+            Node::Matrix { rows, cols, data } => self.visit_matrix(rows, cols, data),
+            Node::Lambda { params, box body } => self.visit_lambda(params, body),
+            Node::Equation(box l, box r) => self.visit_equation(l, r),
             Node::FunctionCall { name, args } => self.visit_function_call(name, args)
         }
     }
@@ -28,21 +34,35 @@ pub trait ExpressionModfierVisitor {
 
     fn visit_var(&mut self, name: String) -> Node { Node::Var(name) }
 
+    fn visit_str(&mut self, s: String) -> Node { Node::Str(s) }
+
     fn visit_vec(&mut self, v: Vec<Node>) -> Node { Node::Vector(v.into_iter().map(|n| self.visit_node(n)).collect()) }
 
+    fn visit_matrix(&mut self, rows: usize, cols: usize, data: Vec<Node>) -> Node { Node::Matrix { rows, cols, data: data.into_iter().map(|n| self.visit_node(n)).collect() } }
+
+    fn visit_lambda(&mut self, params: Vec<String>, body: Node) -> Node { Node::Lambda { params, body: Box::new(self.visit_node(body)) } }
+
+    fn visit_equation(&mut self, l: Node, r: Node) -> Node { Node::Equation(Box::new(self.visit_node(l)), Box::new(self.visit_node(r))) }
+
     fn visit_function_call(&mut self, name: String, args: Vec<Node>) -> Node { Node::FunctionCall { name, args: args.into_iter().map(|n| self.visit_node(n)).collect() } }
 
 
 }
 
+/// Anything without a value, like dividing by zero, is left as it is and the first one found is kept as an error
 pub struct DefaultSimplifyVisitor<'a> {
-    script: &'a Script
+    script: &'a Script,
+    error: Option<DSLError>
 }
 
 impl<'a> DefaultSimplifyVisitor<'a> {
 
     pub fn new(script: &'a Script) -> Self {
-        DefaultSimplifyVisitor{ script }
+        DefaultSimplifyVisitor{ script, error: None }
+    }
+
+    pub fn take_error(&mut self) -> Option<DSLError> {
+        self.error.take()
     }
 
 }
@@ -56,7 +76,11 @@ impl<'a> ExpressionModfierVisitor for DefaultSimplifyVisitor<'a> {
             Node::Num(n) => self.visit_num(n),
             Node::Float(n) => self.visit_float(n),
             Node::Var(name) => self.visit_var(name),
+            Node::Str(s) => self.visit_str(s),
             Node::Vector(v) => self.visit_vec(v),
+            Node::Matrix { rows, cols, data } => self.visit_matrix(rows, cols, data),
+            Node::Lambda { params, box body } => self.visit_lambda(params, body),
+            Node::Equation(box l, box r) => self.visit_equation(l, r),
             Node::FunctionCall { name, args } => self.visit_function_call(name, args)
         };
 
@@ -68,21 +92,53 @@ impl<'a> ExpressionModfierVisitor for DefaultSimplifyVisitor<'a> {
         }
     }
 
-    fn visit_vec(&mut self, v: Vec<Node>) -> Node {
+    // Checked once both sides are simplified so "1 / (2 - 2)" is caught too
+    fn visit_op(&mut self, op_type: String, l: Node, r: Node) -> Node {
+        let (l, r) = (self.visit_node(l), self.visit_node(r));
+
+        let message = match (op_type.as_str(), &l, &r) {
+            ("/", _, Node::Num(b)) if b.is_zero() => Some("division by zero"),
+            ("^", Node::Num(a), Node::Num(b)) if a.is_zero() && *b < Number::Int(0) => Some("zero to a negative power"),
+            _ => None
+        };
+
+        let result = Node::Op(op_type, Box::new(l), Box::new(r));
+        if let (Some(message), None) = (message, &self.error) {
+            self.error = Some(DSLError::RuntimeException { message: message.to_string(), expression: Expression::new(result.clone()) });
+        }
+        result
+    }
 
-        if v.len() == 1 {
-            return self.visit_node(v[0].clone());
-        } else {
-            Node::Vector(v.into_iter().map(|n| self.visit_node(n)).collect())
+    // Variables that have been assigned a value are replaced by it
+    fn visit_var(&mut self, name: String) -> Node {
+        match self.script.get_bindings().get(&name) {
+            Some(value) => self.visit_node(value.get_root_node().clone()),
+            None => Node::Var(name)
         }
     }
 
+    // The body of a lambda is only simplified once it's applied, until then its parameters have no value and could be mistaken for variables of the script
+    fn visit_lambda(&mut self, params: Vec<String>, body: Node) -> Node {
+        VariableReplacer::new(self.script.get_bindings().clone()).with_script(self.script).visit_lambda(params, body)
+    }
+
     // We always want to "simplify" function calls by applying them, or else what's the point in having them
     fn visit_function_call(&mut self, name: String, args: Vec<Node>) -> Node {
 
+        // A variable in call position is applied the same way as when it's passed in as an argument, so with "sq = (x) => x^2" then "sq(3)" is 9
+        if self.script.get_bindings().get(&name).is_some() {
+            let applied = VariableReplacer::new(self.script.get_bindings().clone()).with_script(self.script).visit_function_call(name.clone(), args.clone());
+            // A lambda given the wrong number of arguments can't be applied
+            if !matches!(&applied, Node::FunctionCall { name: n, .. } if *n == name) {
+                return self.visit_node(applied);
+            }
+        }
+
         let args = args.into_iter().map(|n| self.visit_node(n)).collect::<Vec<Node>>();
         if let Some(result) = self.script.exec_function(name.as_str(), args.iter().map(|n| Expression::new(n.clone())).collect()) {
-            self.visit_node(result.get_root_node().clone())
+            // Same as eval, a function that hands back the call it was given can't simplify it any further
+            let call = Node::FunctionCall { name, args };
+            if result.get_root_node().deep_eq(&call) { call } else { self.visit_node(result.get_root_node().clone()) }
         }else {
             Node::FunctionCall { name, args }
         }
@@ -104,7 +160,11 @@ pub trait ImmutableExpressionVisitor<T> {
                 Node::Num(n) => self.visit_num(n),
                 Node::Float(n) => self.visit_float(n),
                 Node::Var(name) => self.visit_var(name),
+                Node::Str(s) => self.visit_str(s),
                 Node::Vector(v) => self.visit_vec(v),
+                Node::Matrix { rows, cols, data } => self.visit_matrix(*rows, *cols, data),
+                Node::Lambda { params, box body } => self.visit_lambda(params, body),
+                Node::Equation(box l, box r) => self.visit_equation(l, r),
                 Node::FunctionCall { name, args } => self.visit_function_call(name, args)
             }
         }
@@ -114,24 +174,38 @@ pub trait ImmutableExpressionVisitor<T> {
         fn visit_num(&self, n: &Number) -> T;
         fn visit_float(&self, n: &f64) -> T;
         fn visit_var(&self, name: &String) -> T;
+        fn visit_str(&self, s: &str) -> T;
         fn visit_vec(&self, v: &Vec<Node>) -> T;
+        fn visit_matrix(&self, rows: usize, cols: usize, data: &[Node]) -> T;
+        fn visit_lambda(&self, params: &[String], body: &Node) -> T;
+        fn visit_equation(&self, l: &Node, r: &Node) -> T;
         fn visit_function_call(&self, name: &String, args: &Vec<Node>) -> T;
     
 }
 
 
-pub struct VariableReplacer {
-    symbol_table: SymbolTable
+pub struct VariableReplacer<'a> {
+    symbol_table: SymbolTable,
+    script: Option<&'a Script> // Where to look up the names of functions, without one every name is a variable
 }
 
-impl VariableReplacer {
+impl<'a> VariableReplacer<'a> {
 
     pub fn new(symbol_table: SymbolTable) -> Self {
-        Self { symbol_table }
+        Self { symbol_table, script: None }
+    }
+
+    pub fn with_script(mut self, script: &'a Script) -> Self {
+        self.script = Some(script);
+        self
+    }
+
+    fn is_function(&self, name: &str) -> bool {
+        self.script.is_some_and(|s| s.has_function(name))
     }
 }
 
-impl ExpressionModfierVisitor for VariableReplacer {
+impl<'a> ExpressionModfierVisitor for VariableReplacer<'a> {
 
     fn visit_var(&mut self, name: String) -> Node {
         if let Some(replacement) = self.symbol_table.get(&name) {
@@ -141,6 +215,101 @@ impl ExpressionModfierVisitor for VariableReplacer {
         }
     }
 
+    // A parameter shadows any variable with the same name, and is renamed if it would capture a variable in something being substituted in
+    fn visit_lambda(&mut self, params: Vec<String>, body: Node) -> Node {
+        let mut inner = self.symbol_table.clone();
+        for param in params.iter() {
+            inner.remove(param);
+        }
+
+        let captured = inner.values().flat_map(|e| e.get_root_node().free_variables()).collect::<HashSet<String>>();
+        let mut taken = captured.iter().chain(params.iter()).cloned().chain(body.free_variables()).collect::<HashSet<String>>();
+        let mut renames = SymbolTable::new();
+
+        let params = params.into_iter().map(|param| {
+            if !captured.contains(&param) { return param; }
+
+            let fresh = (1..).map(|i| format!("{}{}", param, i)).find(|name| !taken.contains(name)).unwrap();
+            taken.insert(fresh.clone());
+            renames.insert(param, Expression::new(Node::Var(fresh.clone())));
+            fresh
+        }).collect();
+
+        let body = VariableReplacer::new(renames).visit_node(body);
+        Node::Lambda { params, body: Box::new(VariableReplacer { symbol_table: inner, script: self.script }.visit_node(body)) }
+    }
+
+    // Functions can be passed in as arguments, a lambda is applied straight away and the name of a defined function is called by that name instead.
+    // Otherwise a known function keeps its name and any other value is implicit multiplication, so "t(4, 2, 0)" with t = 2 is "2 * (4, 2, 0)"
+    fn visit_function_call(&mut self, name: String, args: Vec<Node>) -> Node {
+        let args = args.into_iter().map(|n| self.visit_node(n)).collect::<Vec<Node>>();
+
+        match self.symbol_table.get(&name).map(|e| e.get_root_node()) {
+            Some(Node::Lambda { params, box body }) => apply_lambda(params, body, &args, self.script).unwrap_or(Node::FunctionCall { name, args }),
+            Some(Node::Var(function_name)) if self.is_function(function_name) => Node::FunctionCall { name: function_name.clone(), args },
+            Some(_) if self.is_function(&name) => Node::FunctionCall { name, args },
+            Some(value) => {
+                let operand = if args.len() == 1 { args.into_iter().next().unwrap() } else { Node::Vector(args) };
+                Node::Op("*".to_string(), Box::new(value.clone()), Box::new(operand))
+            },
+            None => Node::FunctionCall { name, args }
+        }
+    }
+
+}
+
+/// Replaces "ans" with the most recent result in history and "ans(n)" with the n-th, counting from 1.
+/// References to results that don't exist yet are left alone
+pub struct HistoryReplacer<'a> {
+    history: &'a [Expression]
+}
+
+impl<'a> HistoryReplacer<'a> {
+
+    pub fn new(history: &'a [Expression]) -> Self {
+        Self { history }
+    }
+}
+
+impl<'a> ExpressionModfierVisitor for HistoryReplacer<'a> {
+
+    fn visit_var(&mut self, name: String) -> Node {
+        match self.history.last() {
+            Some(result) if name == "ans" => result.get_root_node().clone(),
+            _ => Node::Var(name)
+        }
+    }
+
+    // A lambda with a parameter called ans means something else by it
+    fn visit_lambda(&mut self, params: Vec<String>, body: Node) -> Node {
+        let body = if params.iter().any(|p| p == "ans") { body } else { self.visit_node(body) };
+        Node::Lambda { params, body: Box::new(body) }
+    }
+
+    fn visit_function_call(&mut self, name: String, args: Vec<Node>) -> Node {
+        if let (true, [Node::Num(Number::Int(n))]) = (name == "ans", args.as_slice()) {
+            if let Some(result) = usize::try_from(*n).ok().and_then(|n| n.checked_sub(1)).and_then(|i| self.history.get(i)) {
+                return result.get_root_node().clone();
+            }
+        }
+
+        Node::FunctionCall { name, args: args.into_iter().map(|n| self.visit_node(n)).collect() }
+    }
+}
+
+/// Substitutes args in for the parameters of a lambda, None if the wrong number of arguments are given.
+/// A parameter given the name of a function defined in script can be called inside the body
+pub fn apply_lambda(params: &[String], body: &Node, args: &[Node], script: Option<&Script>) -> Option<Node> {
+    if params.len() != args.len() {
+        return None;
+    }
+
+    let mut symbol_table = SymbolTable::new();
+    for (param, arg) in params.iter().zip(args.iter()) {
+        symbol_table.insert(param.clone(), Expression::new(arg.clone()));
+    }
+
+    Some(VariableReplacer { symbol_table, script }.visit_node(body.clone()))
 }
 
 
@@ -149,6 +318,7 @@ mod tests {
     use super::*;
     use crate::model::expression_builder::*;
     use crate::parsing::parser::parse_statement;
+    use crate::builtin::base::base_config;
 
     #[test]
     fn test_remove_unneeded_vec() {
@@ -178,6 +348,57 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_lambda_substitution() {
+        let x = || var("x".to_string());
+        let y = || var("y".to_string());
+
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.insert("y".to_string(), Expression::new(x()));
+        symbol_table.insert("x".to_string(), Expression::new(num(5)));
+        let mut replacer = VariableReplacer::new(symbol_table);
+
+        // x is bound by the lambda so it isn't replaced, and has to be renamed so it doesn't capture the x being substituted for y
+        let result = replacer.visit_node(lambda(&["x"], add(x(), y())));
+        assert!(result.deep_eq(&lambda(&["x1"], add(var("x1".to_string()), x()))), "{}", result.to_string());
+
+        let result = replacer.visit_node(add(x(), lambda(&["y"], y())));
+        assert!(result.deep_eq(&add(num(5), lambda(&["y"], y()))), "{}", result.to_string());
+
+        let mut symbol_table = SymbolTable::new();
+        symbol_table.insert("f".to_string(), Expression::new(lambda(&["a"], pow(var("a".to_string()), num(2)))));
+        let result = VariableReplacer::new(symbol_table).visit_node(func_call("f".to_string(), vec![num(3)]));
+        assert!(result.deep_eq(&pow(num(3), num(2))));
+    }
+
+    #[test]
+    fn test_higher_order_functions() {
+        let mut script = base_config();
+        script.merge(&Script::parse("apply(f, x) = f(x)
+            compose(f, g, x) = f(g(x))
+            square(x) = x^2
+            apply((x) => x^2, 3)
+            compose((a) => a + 1, (b) => 2b, 5)
+            apply(square, 4)
+            compose(square, (x) => x - 1, 4)").unwrap());
+        script.run().unwrap();
+
+        for (i, expected) in [9, 11, 16, 9].iter().enumerate() {
+            assert!(script.get_expression(i).unwrap().get_root_node().deep_eq(&num(*expected)), "{}", script.get_expression(i).unwrap().to_string());
+        }
+
+        // Only a lambda or the name of a function is called, any other argument is multiplied
+        let mut script = base_config();
+        script.merge(&Script::parse("h(x) = x(2)
+            f(x) = x(x + 1)
+            h(y)
+            f(t)").unwrap());
+        script.run().unwrap();
+
+        assert_eq!(script.get_expression(0).unwrap().to_string(), "y * 2");
+        assert_eq!(script.get_expression(1).unwrap().to_string(), "t * (t + 1)");
+    }
+
     #[test]
     fn test_variable_substitution() {
         let test_script = "f(x) = x^2
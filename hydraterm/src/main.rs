@@ -1,41 +1,20 @@
 use std::env;
 use std::error::Error;
-use std::fmt::Display;
 use std::fs::File;
 use std::io::{Write, self, Stdout, Read};
+use std::path::Path;
 use crossterm::event::{Event, KeyModifiers, KeyCode, KeyEventKind};
 use crossterm::style::Print;
 use crossterm::{ cursor, execute, event::read};
 use crossterm::terminal::{Clear, ClearType, size, enable_raw_mode, disable_raw_mode};
 use hydralang::builtin::base::base_config;
-use hydralang::model::{script, Script};
+use hydralang::model::Script;
+use hydralang::parsing::loader::ModuleLoader;
 
+// Errors in what's typed in are shown in the window instead, so the only way out is to exit
 #[derive(Debug)]
 enum WindowReturn {
-    Exit,
-    Error(Box<dyn Error>)
-}
-
-impl Display for WindowReturn {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            WindowReturn::Exit => Ok(()),
-            WindowReturn::Error(e) => e.fmt(f),
-        }
-    }
-}
-
-impl Error for WindowReturn {
-    fn source(&self) -> Option<&(dyn Error + 'static)> {
-        match self {
-            WindowReturn::Exit => None,
-            WindowReturn::Error(e) => Some(e.as_ref()),
-        }
-    }
-
-    fn cause(&self) -> Option<&dyn Error> {
-        self.source()
-    }
+    Exit
 }
 
 fn handle_event(app: &mut Application, event: Event) -> Result<(), WindowReturn> {
@@ -67,12 +46,29 @@ fn handle_event(app: &mut Application, event: Event) -> Result<(), WindowReturn>
 
                 if !(app.input_buffer.trim() == "") {
 
-                    let new_statement = script::Script::parse(app.input_buffer.as_str())
-                        .map_err(|e| WindowReturn::Error(Box::new(e) as Box<dyn Error>))?;
+                    // A line that can't be parsed or run is left in the input to be fixed rather than added to the script.
+                    // The loader goes back too, otherwise anything the line imported would count as already imported
+                    let (previous, previous_loader) = (app.script.clone(), app.loader.clone());
+
+                    let (new_statement, errors) = app.script.parse_continuation_with_loader(app.input_buffer.as_str(), &mut app.loader);
+                    if let Some(error) = errors.first() {
+                        app.error = Some(error.to_string());
+                        app.loader = previous_loader;
+                        return Ok(());
+                    }
 
                     app.script.merge(&new_statement);
-                    app.input_buffer.clear();
-                    app.script.run();
+                    match app.script.run() {
+                        Ok(()) => {
+                            app.input_buffer.clear();
+                            app.error = None;
+                        },
+                        Err(error) => {
+                            app.script = previous;
+                            app.loader = previous_loader;
+                            app.error = Some(error.to_string());
+                        }
+                    }
                 
                 }
             }
@@ -88,7 +84,9 @@ fn handle_event(app: &mut Application, event: Event) -> Result<(), WindowReturn>
 struct Application{
     pub stdout: Stdout,
     pub input_buffer: String,
-    pub script: Script
+    pub script: Script,
+    pub loader: ModuleLoader,
+    pub error: Option<String> // The last thing typed in that couldn't be parsed or run, shown above the input
 }
 
 impl Application {
@@ -97,7 +95,10 @@ impl Application {
         Self {
             stdout: io::stdout(),
             input_buffer: String::new(),
-            script: base_config()
+            script: base_config(),
+            // Kept for the whole session so importing a module twice doesn't define everything in it twice
+            loader: ModuleLoader::default().with_directory(&env::current_dir().unwrap_or_default()),
+            error: None
         }
     }
 
@@ -128,24 +129,31 @@ fn mainloop() -> Result<(), Box<dyn Error>> {
         }
 
 
+        if let Some(error) = &app.error {
+            let lines = error.lines().collect::<Vec<&str>>();
+            for (i, line) in lines.iter().enumerate() {
+                execute!(
+                    app.stdout,
+                    cursor::MoveTo(0, (rows - 3).saturating_sub((lines.len() - i) as u16)),
+                    Print(line.to_string())
+                )?;
+            }
+        }
+
         execute!(
             app.stdout,
             cursor::MoveTo(0, rows - 3),
             Print("─".repeat(cols as usize)),
             cursor::MoveTo(0, rows - 2),
             Print(app.input_buffer.clone()),
-            cursor::MoveTo(app.input_buffer.len() as u16, rows - 2)
+            cursor::MoveTo(app.input_buffer.chars().count() as u16, rows - 2)
         )?;
 
 
         // This program doesn't really have state updates outside events so we don't need to check for that
         let event = read()?;
-        let event_handle_result = handle_event(&mut app, event);
-        if let Err(e) = event_handle_result {
-            match e {
-                WindowReturn::Exit => return Result::Ok(()),
-                WindowReturn::Error(e) => return Result::Err(e),
-            }
+        if let Err(WindowReturn::Exit) = handle_event(&mut app, event) {
+            return Result::Ok(());
         }
 
         app.stdout.flush()?;
@@ -169,20 +177,35 @@ fn main() -> Result<(), Box<dyn Error>>{
         disable_raw_mode()?;
 
     } else {
-        let mut file = File::open(args.get(1).unwrap())?;
+        let path = Path::new(args.get(1).unwrap());
+        let mut file = File::open(path)?;
         println!("Executing file at {}", args.get(0).unwrap());
         let mut source = String::new();
         file.read_to_string(&mut source)?;
 
         let mut base = base_config();
-        let script = Script::parse(&source)?;
+        // Imports are looked for next to the file being executed first
+        let mut loader = ModuleLoader::default().with_directory(path.parent().unwrap_or(Path::new(".")));
+        let (script, errors) = base.parse_continuation_with_loader(&source, &mut loader);
+
+        for error in errors.iter() {
+            eprintln!("{}\n", error);
+        }
 
         base.merge(&script);
 
-        base.run();
+        let result = base.run();
 
         println!("{}", base.to_string());
 
+        if let Err(error) = &result {
+            eprintln!("{}\n", error);
+        }
+
+        if !errors.is_empty() || result.is_err() {
+            std::process::exit(1);
+        }
+
     }
 
     Ok(())